@@ -1,190 +1,209 @@
-use lazy_static::lazy_static;
-use sqlite;
-use std::{
-    fs,
-    io::{self, Write},
-};
-use syntect;
-use syntect::highlighting::{Style, ThemeSet};
-use syntect::parsing::SyntaxSet;
+use serde::Serialize;
 
+use crate::highlight::Highlighter;
 use crate::language::Language;
 use crate::utils;
+use crate::view::{self, View};
 
-const DEFAULT_PATH: &str = ".capture";
-const INDEX_FILE_PATH: &str = ".capture/index.sql";
-
-fn get_connection() -> io::Result<sqlite::Connection> {
-    match sqlite::open(INDEX_FILE_PATH) {
-        Ok(conn) => Ok(conn),
-        Err(e) => Err(io::Error::new(io::ErrorKind::Other, e)),
-    }
-}
-
-pub fn setup() {
-    let conn = get_connection().unwrap();
-    conn.execute("CREATE TABLE bookmarks (id TEXT PRIMARY KEY, name TEXT, lang TEXT);")
-        .unwrap();
-}
-
+#[derive(Debug, Clone, PartialEq)]
 pub struct Bookmark {
     pub id: String,
     pub name: String,
     pub lang: Language,
     pub content: Vec<String>,
+    pub description: String,
+    pub tags: Vec<String>,
+    /// Free-form notes, in markdown
+    pub notes: String,
+    /// Related links, like the issue or pull request the code comes from
+    pub urls: Vec<String>,
+    /// Ids of the previous versions of the content, oldest first
+    pub history: Vec<String>,
+    /// File the content was captured from, empty if unknown
+    pub source: String,
+    /// Line of `source` each line of the content comes from, starting at 1.
+    /// Empty once the content no longer matches the file
+    pub origin: Vec<usize>,
+    /// When the bookmark was last changed, in seconds since the Unix epoch.
+    /// 0 if unknown
+    pub updated: u64,
 }
 
 impl Bookmark {
-    fn load(pair: &[(&str, Option<&str>)]) -> Self {
-        let id = pair[0].1.unwrap();
-        let name = pair[1].1.unwrap();
-        let extension = pair[2].1.unwrap();
-
-        let path = format!(".capture/{}", id);
-        let content: Vec<String> = utils::read_lines(path)
-            .unwrap()
-            .map(|f| f.unwrap())
-            .collect();
-
+    pub fn new(name: &str, content: &[String], lang: Language) -> Self {
         Bookmark {
-            id: id.to_string(),
+            id: utils::content_hash(content),
             name: name.to_string(),
-            content,
-            lang: Language::from_extension(extension),
+            lang,
+            content: content.to_vec(),
+            description: String::new(),
+            tags: Vec::new(),
+            notes: String::new(),
+            urls: Vec::new(),
+            history: Vec::new(),
+            source: String::new(),
+            origin: Vec::new(),
+            updated: utils::now(),
         }
     }
 
-    fn get_path(&self) -> String {
-        format!("{}/{}", DEFAULT_PATH, self.id)
-    }
-
-    pub fn print(&self, display_content: bool) {
-        println!("Bookmark: {} - {}", self.name, self.id);
-
-        if display_content {
-            lazy_static! {
-                static ref PS: SyntaxSet = SyntaxSet::load_defaults_newlines();
-                static ref TS: ThemeSet = ThemeSet::load_defaults();
-            }
-
-            let syntax = PS
-                .find_syntax_by_extension(&self.lang.to_extension())
-                .unwrap();
-            let mut h = syntect::easy::HighlightLines::new(syntax, &TS.themes["base16-ocean.dark"]);
-
-            for line in &self.content {
-                let ranges: Vec<(Style, &str)> = h.highlight_line(line, &PS).unwrap();
-                let escaped = syntect::util::as_24_bit_terminal_escaped(&ranges[..], false);
-
-                println!("{}", escaped);
-            }
-            print!("\n");
+    /// Replaces the content, keeping the current one as a previous version.
+    /// Nothing is recorded if the content did not change.
+    pub fn set_content(&mut self, content: &[String]) {
+        let id = utils::content_hash(content);
+        if id == self.id {
+            return;
         }
-    }
-}
 
-fn exists(name: &String) -> io::Result<bool> {
-    let mut num_matches: usize = 0;
-
-    let conn = get_connection()?;
-    let statement = format!("SELECT * FROM bookmarks WHERE name = '{}'", name);
-
-    match conn.iterate(statement, |_| {
-        num_matches += 1;
-        true
-    }) {
-        Ok(()) => (),
-        Err(e) => return Err(io::Error::new(io::ErrorKind::Other, e)),
-    };
-
-    if num_matches > 1 {
-        return Err(io::Error::new(
-            io::ErrorKind::Other,
-            "More than one bookmark with the same name",
-        ));
+        self.history.push(std::mem::replace(&mut self.id, id));
+        self.content = content.to_vec();
+        self.origin.clear();
+        self.touch();
     }
 
-    Ok(num_matches == 1)
-}
-
-pub fn create(name: &String, lines: &Vec<String>, lang: &Language) -> io::Result<()> {
-    let id = utils::merkle_tree_hash(&lines);
-
-    if exists(&name)? {
-        let err_msg = format!("Bookmark with name: '{}' already exists", name);
-        return Err(io::Error::new(io::ErrorKind::AlreadyExists, err_msg));
+    /// Records that the bookmark was changed now.
+    pub fn touch(&mut self) {
+        self.updated = utils::now();
     }
 
-    let path = format!(".capture/{}", id);
-    let mut file = fs::File::create(&path)?;
+    /// Number of the current version, starting at 1.
+    pub fn version(&self) -> usize {
+        self.history.len() + 1
+    }
 
-    for line in lines {
-        let line = format!("{}\n", line);
-        file.write(line.as_bytes())?;
+    /// Whether `query` appears in the name, metadata or content of the
+    /// bookmark, ignoring case.
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        let fields = [&self.name, &self.description, &self.notes];
+
+        fields
+            .into_iter()
+            .chain(&self.tags)
+            .chain(&self.urls)
+            .chain(&self.content)
+            .any(|text| text.to_lowercase().contains(&query))
     }
 
-    let conn = get_connection()?;
-
-    let statement = format!(
-        "INSERT INTO bookmarks (id, name, lang) VALUES ('{}', '{}', '{}');",
-        id,
-        name,
-        lang.to_extension()
-    );
-    match conn.execute(&statement) {
-        Ok(()) => Ok(()),
-        Err(e) => Err(io::Error::new(io::ErrorKind::Other, e)),
+    fn has_details(&self) -> bool {
+        !(self.description.is_empty()
+            && self.tags.is_empty()
+            && self.urls.is_empty()
+            && self.notes.is_empty())
     }
-}
 
-pub fn delete(name: &String) -> io::Result<()> {
-    let bookmark = match get_bookmark(&name)? {
-        Some(bk) => bk,
-        None => {
-            let err_msg = format!("Bookmark with name: '{}' does not exist.", name);
-            return Err(io::Error::new(io::ErrorKind::NotFound, err_msg));
+    pub fn to_json(&self) -> serde_json::Value {
+        #[derive(Serialize)]
+        struct Json<'a> {
+            name: &'a str,
+            id: &'a str,
+            lang: &'a str,
+            version: usize,
+            description: &'a str,
+            tags: &'a [String],
+            notes: &'a str,
+            urls: &'a [String],
+            #[serde(skip_serializing_if = "str::is_empty")]
+            source: &'a str,
+            #[serde(skip_serializing_if = "<[usize]>::is_empty")]
+            origin: &'a [usize],
+            #[serde(skip_serializing_if = "Option::is_none")]
+            updated: Option<String>,
+            content: &'a [String],
         }
-    };
-
-    fs::remove_file(bookmark.get_path())?;
 
-    let conn = get_connection()?;
-    let statement = format!("DELETE FROM bookmarks WHERE name = '{}';", name);
-
-    match conn.execute(&statement) {
-        Ok(()) => Ok(()),
-        Err(e) => Err(io::Error::new(io::ErrorKind::Other, e)),
+        serde_json::json!(Json {
+            name: &self.name,
+            id: &self.id,
+            lang: &self.lang.to_extension(),
+            version: self.version(),
+            description: &self.description,
+            tags: &self.tags,
+            notes: &self.notes,
+            urls: &self.urls,
+            source: &self.source,
+            origin: &self.origin,
+            updated: (self.updated > 0).then(|| utils::format_date(self.updated)),
+            content: &self.content,
+        })
     }
-}
 
-pub fn get_bookmark(name: &String) -> io::Result<Option<Bookmark>> {
-    let conn = get_connection()?;
+    /// Lines shown for the bookmark: its name, then with `display_content`
+    /// its metadata and highlighted content.
+    pub fn display(
+        &self,
+        display_content: bool,
+        highlighter: &Highlighter,
+        view: &View,
+    ) -> Vec<String> {
+        let mut lines = Vec::new();
+        if view.header {
+            lines.extend(view::header(self));
+        } else {
+            lines.push(format!("Bookmark: {} - {}", self.name, self.id));
+        }
 
-    let mut bookmark: Option<Bookmark> = None;
+        if display_content {
+            if !self.description.is_empty() {
+                lines.push(self.description.clone());
+            }
+            if !self.tags.is_empty() {
+                lines.push(format!("Tags: {}", self.tags.join(", ")));
+            }
+            for url in &self.urls {
+                lines.push(format!("Link: {}", url));
+            }
+            if !self.notes.is_empty() {
+                lines.push(String::new());
+                lines.extend(self.notes.trim_end().lines().map(String::from));
+            }
+            if self.has_details() {
+                lines.push(String::new());
+            }
 
-    let statement = format!("SELECT * FROM bookmarks WHERE name = '{}'", name);
-    match conn.iterate(statement, |pairs| {
-        bookmark = Some(Bookmark::load(&pairs));
-        true
-    }) {
-        Ok(()) => (),
-        Err(e) => return Err(io::Error::new(io::ErrorKind::Other, e)),
-    };
+            let content = highlighter.highlight(&self.lang, &self.content);
+            match view.line_numbers {
+                Some(numbers) => lines.extend(view::gutter(self, &content, numbers)),
+                None => lines.extend(content),
+            }
+            lines.push(String::new());
+        }
 
-    Ok(bookmark)
+        lines
+    }
 }
 
-pub fn get_all_bookmarks() -> io::Result<Vec<Bookmark>> {
-    let conn = get_connection()?;
+#[cfg(test)]
+mod tests {
+    use super::Bookmark;
+    use crate::language::Language;
+
+    fn bookmark() -> Bookmark {
+        let content = vec![String::from("fn parse_args() {"), String::from("}")];
+        let mut bookmark = Bookmark::new("cli/args", &content, Language::Rust);
+        bookmark.description = String::from("Reads the command line");
+        bookmark.notes = String::from("Uses **clap** derive");
+        bookmark.urls = vec![String::from("https://example.com/issues/7")];
+        bookmark
+    }
 
-    let mut bookmarks = Vec::new();
+    #[test]
+    fn matches_metadata_and_content() {
+        let bookmark = bookmark();
+        for query in ["CLI/", "command line", "clap", "issues/7", "parse_args"] {
+            assert!(bookmark.matches(query), "{}", query);
+        }
+        assert!(!bookmark.matches("python"));
+    }
 
-    match conn.iterate("SELECT * FROM bookmarks;", |pairs| {
-        let bookmark = Bookmark::load(&pairs);
-        bookmarks.push(bookmark);
-        true
-    }) {
-        Ok(()) => Ok(bookmarks),
-        Err(e) => return Err(io::Error::new(io::ErrorKind::Other, e)),
+    #[test]
+    fn converts_to_json() {
+        let json = bookmark().to_json();
+        assert_eq!(json["name"], "cli/args");
+        assert_eq!(json["lang"], "rs");
+        assert_eq!(json["version"], 1);
+        assert_eq!(json["description"], "Reads the command line");
+        assert_eq!(json["urls"][0], "https://example.com/issues/7");
+        assert_eq!(json["content"][0], "fn parse_args() {");
     }
 }
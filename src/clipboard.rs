@@ -0,0 +1,125 @@
+use base64::Engine;
+use std::env;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+/// How text was put on the clipboard.
+#[derive(Debug, PartialEq)]
+pub enum Method {
+    /// Piped to a command like xclip or wl-copy
+    Command(String),
+    /// Sent to the terminal as an OSC 52 escape, which also reaches the
+    /// local clipboard over SSH
+    Osc52,
+}
+
+/// Command to copy with: `$CAPTURE_COPY_COMMAND`, or the one of the running
+/// desktop. Over SSH the desktop is not the user's, so there is none.
+fn copy_command() -> Option<String> {
+    if let Some(command) = env::var("CAPTURE_COPY_COMMAND")
+        .ok()
+        .filter(|command| !command.trim().is_empty())
+    {
+        return Some(command);
+    }
+
+    if env::var_os("SSH_TTY").is_some() || env::var_os("SSH_CONNECTION").is_some() {
+        None
+    } else if env::var_os("WAYLAND_DISPLAY").is_some() {
+        Some(String::from("wl-copy"))
+    } else if env::var_os("DISPLAY").is_some() {
+        Some(String::from("xclip -selection clipboard"))
+    } else if cfg!(target_os = "macos") {
+        Some(String::from("pbcopy"))
+    } else {
+        None
+    }
+}
+
+/// Puts `text` on the clipboard, falling back to OSC 52 when there is no
+/// command to copy with or it is not installed.
+pub fn copy(text: &str) -> io::Result<Method> {
+    if let Some(command) = copy_command() {
+        match copy_with(text, &command) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            result => return result.map(|_| Method::Command(command)),
+        }
+    }
+
+    let mut tty = OpenOptions::new()
+        .write(true)
+        .open("/dev/tty")
+        .map_err(|e| io::Error::new(e.kind(), format!("No terminal to copy through: {}", e)))?;
+    tty.write_all(osc52(text, env::var_os("TMUX").is_some()).as_bytes())?;
+    tty.flush()?;
+    Ok(Method::Osc52)
+}
+
+/// Pipes `text` to `command`, split on whitespace like `$EDITOR`.
+fn copy_with(text: &str, command: &str) -> io::Result<()> {
+    let mut args = command.split_whitespace();
+    let mut child = Command::new(args.next().unwrap_or_default())
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()?;
+
+    // Dropping stdin closes it, so the command knows the text is complete
+    let written = child.stdin.take().unwrap().write_all(text.as_bytes());
+    let status = child.wait()?;
+    written?;
+
+    if !status.success() {
+        let err_msg = format!("Copy command '{}' exited with {}", command, status);
+        return Err(io::Error::other(err_msg));
+    }
+
+    Ok(())
+}
+
+/// Escape sequence that sets the clipboard to `text`. Inside `tmux` it is
+/// wrapped so tmux passes it on to the terminal.
+fn osc52(text: &str, tmux: bool) -> String {
+    let sequence = format!(
+        "\x1b]52;c;{}\x07",
+        base64::engine::general_purpose::STANDARD.encode(text)
+    );
+
+    if tmux {
+        format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b"))
+    } else {
+        sequence
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{copy_with, osc52};
+    use std::fs;
+
+    #[test]
+    fn copies_with_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("clipboard");
+        let stub = format!("cp /dev/stdin {}", path.display());
+
+        copy_with("fn one() {\n}", &stub).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fn one() {\n}");
+
+        assert!(copy_with("fn one() {}", "false").is_err());
+        assert!(copy_with("fn one() {}", "capture-missing-copy-command").is_err());
+    }
+
+    #[test]
+    fn encodes_osc52() {
+        assert_eq!(
+            osc52("fn one() {}", false),
+            "\x1b]52;c;Zm4gb25lKCkge30=\x07"
+        );
+        assert_eq!(
+            osc52("fn one() {}", true),
+            "\x1bPtmux;\x1b\x1b]52;c;Zm4gb25lKCkge30=\x07\x1b\\"
+        );
+    }
+}
@@ -0,0 +1,198 @@
+use std::env;
+use std::io::{self, IsTerminal};
+use std::str::FromStr;
+use syntect::highlighting::Style;
+
+/// When to highlight code, set with `--color`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorMode {
+    /// Only when printing to a terminal and `NO_COLOR` is not set
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode {
+            "auto" => Ok(ColorMode::Auto),
+            "always" => Ok(ColorMode::Always),
+            "never" => Ok(ColorMode::Never),
+            _ => Err(format!(
+                "Unknown color mode '{}', expected auto, always or never",
+                mode
+            )),
+        }
+    }
+}
+
+/// Colors the terminal code is highlighted for can show.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Colors {
+    /// 24-bit colors
+    TrueColor,
+    /// The 256 colors of xterm
+    Ansi256,
+    /// The 16 basic terminal colors
+    Ansi16,
+    /// Plain text
+    None,
+}
+
+impl Colors {
+    /// Colors to print to stdout with, given `mode` and the environment.
+    pub fn detect(mode: ColorMode) -> Self {
+        let enabled = match mode {
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+            ColorMode::Auto => {
+                io::stdout().is_terminal()
+                    && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                    && env::var("TERM").map_or(true, |term| term != "dumb")
+            }
+        };
+
+        if !enabled {
+            return Colors::None;
+        }
+
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        let term = env::var("TERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            Colors::TrueColor
+        } else if term.contains("256color") {
+            Colors::Ansi256
+        } else {
+            Colors::Ansi16
+        }
+    }
+
+    /// Text of the highlighted `ranges`, with the escapes that color them.
+    pub fn escape(self, ranges: &[(Style, &str)]) -> String {
+        let mut escaped = String::new();
+        for (style, text) in ranges {
+            let (r, g, b) = (style.foreground.r, style.foreground.g, style.foreground.b);
+            match self {
+                Colors::TrueColor => escaped.push_str(&format!("\x1b[38;2;{};{};{}m", r, g, b)),
+                Colors::Ansi256 => escaped.push_str(&format!("\x1b[38;5;{}m", ansi256(r, g, b))),
+                Colors::Ansi16 => escaped.push_str(&format!("\x1b[{}m", ansi16(r, g, b))),
+                Colors::None => (),
+            }
+            escaped.push_str(text);
+        }
+
+        if self != Colors::None && !ranges.is_empty() {
+            escaped.push_str("\x1b[0m");
+        }
+        escaped
+    }
+}
+
+/// Closest color of the xterm palette: either the 6x6x6 color cube or the
+/// gray ramp, whichever is nearer.
+fn ansi256(r: u8, g: u8, b: u8) -> u8 {
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    let level = |value: u8| -> usize {
+        (0..LEVELS.len())
+            .min_by_key(|idx| (LEVELS[*idx] as i32 - value as i32).abs())
+            .unwrap()
+    };
+
+    let (cr, cg, cb) = (level(r), level(g), level(b));
+    let cube = (LEVELS[cr], LEVELS[cg], LEVELS[cb]);
+
+    let average = (r as u32 + g as u32 + b as u32) / 3;
+    let gray_idx = (average.saturating_sub(8) / 10).min(23) as u8;
+    let gray_value = 8 + gray_idx * 10;
+    let gray = (gray_value, gray_value, gray_value);
+
+    if distance((r, g, b), gray) < distance((r, g, b), cube) {
+        232 + gray_idx
+    } else {
+        16 + 36 * cr as u8 + 6 * cg as u8 + cb as u8
+    }
+}
+
+/// SGR code of the basic color with the same hue. Themes favour muted
+/// colors, which would all be closest to gray, so only colors with barely any
+/// hue are shown as gray.
+fn ansi16(r: u8, g: u8, b: u8) -> u8 {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+
+    if max - min < 32 {
+        return match max {
+            0..=63 => 30,
+            64..=159 => 90,
+            160..=223 => 37,
+            _ => 97,
+        };
+    }
+
+    let middle = (max as u16 + min as u16) / 2;
+    let on = |value: u8| (value as u16 >= middle) as u8;
+    let code = 30 + on(r) + 2 * on(g) + 4 * on(b);
+    if max > 200 {
+        code + 60
+    } else {
+        code
+    }
+}
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let diff = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+    diff(a.0, b.0) + diff(a.1, b.1) + diff(a.2, b.2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ansi16, ansi256, ColorMode, Colors};
+    use syntect::highlighting::{Color, Style};
+
+    fn style(r: u8, g: u8, b: u8) -> Style {
+        Style {
+            foreground: Color { r, g, b, a: 255 },
+            ..Style::default()
+        }
+    }
+
+    #[test]
+    fn escapes_for_each_depth() {
+        let ranges = [(style(180, 142, 173), "fn"), (style(192, 197, 206), " one")];
+
+        assert_eq!(Colors::None.escape(&ranges), "fn one");
+        assert_eq!(
+            Colors::TrueColor.escape(&ranges),
+            "\x1b[38;2;180;142;173mfn\x1b[38;2;192;197;206m one\x1b[0m"
+        );
+        assert_eq!(
+            Colors::Ansi256.escape(&ranges),
+            "\x1b[38;5;139mfn\x1b[38;5;251m one\x1b[0m"
+        );
+        assert_eq!(
+            Colors::Ansi16.escape(&ranges),
+            "\x1b[35mfn\x1b[37m one\x1b[0m"
+        );
+        assert_eq!(Colors::TrueColor.escape(&[]), "");
+    }
+
+    #[test]
+    fn maps_to_nearest_color() {
+        assert_eq!(ansi256(0, 0, 0), 16);
+        assert_eq!(ansi256(255, 0, 0), 196);
+        assert_eq!(ansi256(128, 128, 128), 244);
+        assert_eq!(ansi16(250, 10, 10), 91);
+        assert_eq!(ansi16(0, 0, 200), 34);
+        assert_eq!(ansi16(143, 161, 179), 36);
+        assert_eq!(ansi16(120, 120, 125), 90);
+    }
+
+    #[test]
+    fn never_colors_when_disabled() {
+        assert_eq!(Colors::detect(ColorMode::Never), Colors::None);
+        assert_ne!(Colors::detect(ColorMode::Always), Colors::None);
+        assert!("sometimes".parse::<ColorMode>().is_err());
+    }
+}
@@ -0,0 +1,74 @@
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Name of the config file inside the config directory.
+const FILE_NAME: &str = "config.toml";
+
+/// User settings, read from `config.toml` in the config directory.
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Theme to highlight code with when `--theme` is not given
+    #[serde(default)]
+    pub theme: Option<String>,
+}
+
+impl Config {
+    /// Config directory: `$CAPTURE_CONFIG_DIR`, or `capture` inside
+    /// `$XDG_CONFIG_HOME` or `~/.config`.
+    pub fn dir() -> Option<PathBuf> {
+        if let Some(dir) = env::var_os("CAPTURE_CONFIG_DIR").filter(|dir| !dir.is_empty()) {
+            return Some(PathBuf::from(dir));
+        }
+
+        env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .map(|dir| dir.join("capture"))
+    }
+
+    /// Reads the config in `dir`. Without a config file every setting has
+    /// its default value.
+    pub fn load(dir: &Path) -> io::Result<Self> {
+        let path = dir.join(FILE_NAME);
+        let config = match fs::read_to_string(&path) {
+            Ok(config) => config,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(e),
+        };
+
+        toml::from_str(&config).map_err(|e| {
+            let err_msg = format!("Invalid config in {}: {}", path.display(), e);
+            io::Error::new(io::ErrorKind::InvalidData, err_msg)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Config;
+    use std::fs;
+    use std::io;
+
+    #[test]
+    fn loads_config() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(Config::load(dir.path()).unwrap(), Config::default());
+
+        fs::write(
+            dir.path().join("config.toml"),
+            "theme = \"InspiredGitHub\"\n",
+        )
+        .unwrap();
+        let config = Config::load(dir.path()).unwrap();
+        assert_eq!(config.theme.as_deref(), Some("InspiredGitHub"));
+
+        fs::write(dir.path().join("config.toml"), "colour = \"red\"\n").unwrap();
+        let err = Config::load(dir.path()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,354 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, PoisonError, RwLock};
+
+/// Name of the file with the user's languages inside the config directory.
+const FILE_NAME: &str = "languages.toml";
+
+/// Definitions of the languages capture knows about out of the box.
+const BUILT_IN: &str = include_str!("languages.toml");
+
+lazy_static! {
+    static ref REGISTRY: RwLock<Vec<Arc<Definition>>> = RwLock::new(built_in());
+}
+
+/// How a language, given its definition, tells where a function ends.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Blocks {
+    /// With the delimiter closing the first one opened
+    #[default]
+    Braces,
+    /// With its indented lines, as in Python
+    Indent,
+    /// With the keyword balancing the ones that open blocks, as in Ruby
+    Keywords,
+    /// With its indented lines and the equations right after it, as in Haskell
+    Clauses,
+    /// With the end of the SQL statement creating it
+    Statements,
+}
+
+/// Format of the values a language captures by key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Keys {
+    Yaml,
+    Toml,
+    Json,
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Comments {
+    /// Start of single line comments, empty if there are none
+    #[serde(default)]
+    pub line: String,
+    /// Start and end of block comments
+    #[serde(default)]
+    pub block: Option<(String, String)>,
+    /// Start of pragmas, which look like block comments but are code
+    #[serde(default)]
+    pub pragma: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Keywords {
+    pub open: Vec<String>,
+    pub close: Vec<String>,
+    /// Opening keywords that only open a block at the start of an expression
+    #[serde(default)]
+    pub modifiers: Vec<String>,
+    /// Keywords whose line's `do` is part of them rather than a block
+    #[serde(default)]
+    pub loops: Vec<String>,
+}
+
+/// Extensions of another language, whose files are in this one when their
+/// content matches.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Shared {
+    pub extensions: Vec<String>,
+    pub content: String,
+    /// `content`, compiled when the definition is parsed
+    #[serde(skip)]
+    content_syntax: Option<Regex>,
+}
+
+impl Shared {
+    /// Whether a file with `extension` and `content` is in this language.
+    pub fn matches(&self, extension: &str, content: &str) -> bool {
+        self.extensions.iter().any(|e| e == extension)
+            && self
+                .content_syntax
+                .as_ref()
+                .is_some_and(|syntax| syntax.is_match(content))
+    }
+}
+
+/// A language, as defined in a `languages.toml` file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Definition {
+    pub name: String,
+    /// Extensions of its files, snippets are saved with the first one
+    pub extensions: Vec<String>,
+    /// Names of files without an extension, ending in `*` to match any suffix
+    #[serde(default)]
+    pub file_names: Vec<String>,
+    /// Other names interpreters, editors or people call it
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    #[serde(default)]
+    pub shared: Option<Shared>,
+    /// Regex matching the line a function starts on, with its name in the
+    /// `name` group or otherwise the first group that matches
+    #[serde(default)]
+    pub function: Option<String>,
+    /// `function`, compiled when the definition is parsed
+    #[serde(skip)]
+    function_syntax: Option<Regex>,
+    /// Regex matching the declarations functions can be nested in, with their
+    /// name as in `function`
+    #[serde(default)]
+    pub class: Option<String>,
+    #[serde(skip)]
+    class_syntax: Option<Regex>,
+    /// Regex matching lines above a function that are part of it
+    #[serde(default)]
+    pub annotation: Option<String>,
+    #[serde(skip)]
+    annotation_syntax: Option<Regex>,
+    /// What makes the body of a function opening no block a single expression
+    #[serde(default)]
+    pub expression: Option<String>,
+    /// Whether functions may have no body at all
+    #[serde(default)]
+    pub optional_body: bool,
+    #[serde(default)]
+    pub comments: Comments,
+    #[serde(default)]
+    pub blocks: Blocks,
+    /// Keywords opening and closing blocks, with `blocks = "keywords"`
+    #[serde(default)]
+    pub keywords: Option<Keywords>,
+    /// Tags of the sections of single-file components
+    #[serde(default)]
+    pub sections: Vec<String>,
+    /// Format of the values captured by key
+    #[serde(default)]
+    pub keys: Option<Keys>,
+    /// Whether code is in fenced blocks, as in Markdown
+    #[serde(default)]
+    pub fences: bool,
+    /// Name of the syntax to highlight it with
+    #[serde(default)]
+    pub syntax: Option<String>,
+}
+
+impl Definition {
+    /// Regex matching the line a function starts on, `None` if the language
+    /// has no functions.
+    pub fn function_syntax(&self) -> Option<&Regex> {
+        self.function_syntax.as_ref()
+    }
+
+    /// Regex matching the declarations functions can be nested in.
+    pub fn class_syntax(&self) -> Option<&Regex> {
+        self.class_syntax.as_ref()
+    }
+
+    /// Regex matching lines above a function that are part of it.
+    pub fn annotation_syntax(&self) -> Option<&Regex> {
+        self.annotation_syntax.as_ref()
+    }
+
+    /// Whether a file called `name` is in this language.
+    pub fn matches_file_name(&self, name: &str) -> bool {
+        self.file_names
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => name.starts_with(prefix),
+                None => pattern == name,
+            })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Definitions {
+    #[serde(default)]
+    language: Vec<Definition>,
+}
+
+/// Parses the definitions of `content`, compiling their regexes and
+/// checking their keywords.
+pub(crate) fn parse(content: &str) -> Result<Vec<Definition>, String> {
+    let mut definitions: Definitions = toml::from_str(content).map_err(|e| e.to_string())?;
+
+    for definition in &mut definitions.language {
+        let name = definition.name.clone();
+        if definition.extensions.is_empty() {
+            return Err(format!("Language '{}' has no extensions", name));
+        }
+        definition.function_syntax = compile(&name, "function", definition.function.as_deref())?;
+        definition.class_syntax = compile(&name, "class", definition.class.as_deref())?;
+        definition.annotation_syntax =
+            compile(&name, "annotation", definition.annotation.as_deref())?;
+        if let Some(shared) = &mut definition.shared {
+            shared.content_syntax = compile(&name, "shared content", Some(&shared.content))?;
+        }
+        if definition.blocks == Blocks::Keywords && definition.keywords.is_none() {
+            return Err(format!(
+                "Language '{}' has keyword blocks but no keywords",
+                name
+            ));
+        }
+    }
+
+    Ok(definitions.language)
+}
+
+/// Compiles the `field` regex of the language called `name`, if it has one.
+fn compile(name: &str, field: &str, regex: Option<&str>) -> Result<Option<Regex>, String> {
+    regex
+        .map(|regex| {
+            Regex::new(regex).map_err(|e| format!("Invalid {} regex of '{}': {}", field, name, e))
+        })
+        .transpose()
+}
+
+fn built_in() -> Vec<Arc<Definition>> {
+    parse(BUILT_IN)
+        .expect("Built-in languages are valid")
+        .into_iter()
+        .map(Arc::new)
+        .collect()
+}
+
+/// `defined` languages along with the `built_in` ones they do not replace.
+/// The former come first, so that they win the extensions they share.
+fn merge(built_in: Vec<Arc<Definition>>, defined: Vec<Definition>) -> Vec<Arc<Definition>> {
+    let replaced = |definition: &Definition| {
+        defined
+            .iter()
+            .any(|other| other.name.eq_ignore_ascii_case(&definition.name))
+    };
+    let built_in: Vec<_> = built_in
+        .into_iter()
+        .filter(|definition| !replaced(definition))
+        .collect();
+
+    defined.into_iter().map(Arc::new).chain(built_in).collect()
+}
+
+/// Adds the languages of `languages.toml` in the config `dir` to the built-in
+/// ones, replacing those with the same name. Without the file only the
+/// built-in languages are known.
+pub fn load(dir: &Path) -> io::Result<()> {
+    let path = dir.join(FILE_NAME);
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    let defined = parse(&content).map_err(|e| {
+        let err_msg = format!("Invalid languages in {}: {}", path.display(), e);
+        io::Error::new(io::ErrorKind::InvalidData, err_msg)
+    })?;
+
+    let mut registry = REGISTRY.write().unwrap_or_else(PoisonError::into_inner);
+    *registry = merge(built_in(), defined);
+    Ok(())
+}
+
+/// First known language whose definition matches `predicate`.
+pub fn find(predicate: impl Fn(&Definition) -> bool) -> Option<Arc<Definition>> {
+    let registry = REGISTRY.read().unwrap_or_else(PoisonError::into_inner);
+    registry
+        .iter()
+        .find(|definition| predicate(definition))
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{built_in, merge, parse, Blocks, Keys};
+
+    #[test]
+    fn parses_built_in_languages() {
+        let languages = built_in();
+        assert_eq!(languages.len(), 28);
+
+        let ruby = languages.iter().find(|language| language.name == "Ruby");
+        let ruby = ruby.unwrap();
+        assert_eq!(ruby.blocks, Blocks::Keywords);
+        assert_eq!(ruby.comments.line, "#");
+        assert!(ruby.matches_file_name("Gemfile"));
+        let syntax = ruby.function_syntax().unwrap();
+        assert_eq!(&syntax.captures("def save!").unwrap()["name"], "save!");
+
+        let json = languages.iter().find(|language| language.name == "Json");
+        assert!(json.unwrap().function_syntax().is_none());
+        assert_eq!(json.unwrap().keys, Some(Keys::Json));
+
+        let java = languages.iter().find(|language| language.name == "Java");
+        let class = java.unwrap().class_syntax().unwrap();
+        assert_eq!(
+            &class.captures("public class Order {").unwrap()["name"],
+            "Order"
+        );
+
+        let cpp = languages.iter().find(|language| language.name == "Cpp");
+        let shared = cpp.unwrap().shared.as_ref().unwrap();
+        assert!(shared.matches("h", "namespace app {\n"));
+        assert!(!shared.matches("h", "int main(void);\n"));
+
+        let dockerfile = languages
+            .iter()
+            .find(|language| language.name == "Dockerfile");
+        assert!(dockerfile.unwrap().matches_file_name("Dockerfile.dev"));
+    }
+
+    #[test]
+    fn replaces_built_in_languages() {
+        let defined = parse(
+            "[[language]]\n\
+             name = \"rust\"\n\
+             extensions = [\"rs\"]\n\
+             function = '''^fn (?P<name>\\w+)'''\n\
+             [[language]]\n\
+             name = \"Zig\"\n\
+             extensions = [\"zig\"]\n",
+        )
+        .unwrap();
+
+        let languages = merge(built_in(), defined);
+        assert_eq!(languages.len(), 29);
+        assert_eq!(languages[0].function.as_deref(), Some(r"^fn (?P<name>\w+)"));
+        assert_eq!(languages[1].name, "Zig");
+        assert!(!languages.iter().any(|language| language.name == "Rust"));
+    }
+
+    #[test]
+    fn rejects_invalid_languages() {
+        let invalid = [
+            "[[language]]\nname = \"Zig\"\nextensions = [\"zig\"]\nfunction = \"fn (\"\n",
+            "[[language]]\nname = \"Zig\"\nextensions = []\n",
+            "[[language]]\nname = \"Zig\"\nextensions = [\"zig\"]\nblocks = \"keywords\"\n",
+            "[[language]]\nname = \"Zig\"\nextensions = [\"zig\"]\nblocks = \"tabs\"\n",
+            "[[language]]\nname = \"Zig\"\nextension = \"zig\"\n",
+        ];
+
+        for content in invalid {
+            assert!(parse(content).is_err(), "{}", content);
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use crate::language::Language;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    static ref FENCE: Regex = Regex::new(r"^( {0,3})(`{3,}|~{3,})\s*([^`]*?)\s*$").unwrap();
+    static ref HEADING: Regex = Regex::new(r"^ {0,3}(#{1,6})(?:\s+(.*?))?(?:\s+#+)?\s*$").unwrap();
+}
+
+/// A fenced code block of a Markdown file.
+#[derive(Debug, PartialEq)]
+pub struct Block {
+    /// Lines of the code between the fences, as indexes. Empty blocks end
+    /// before they start.
+    pub start: usize,
+    pub end: usize,
+    /// Language of the info string after the opening fence
+    pub language: Language,
+}
+
+/// The fenced code block `number` of `lines`, starting at 1. With a
+/// `heading`, only the blocks in the section under it are counted.
+pub fn find_block(lines: &[String], number: usize, heading: Option<&str>) -> Option<Block> {
+    let headings = headings(lines);
+    let (first, last) = match heading {
+        Some(title) => {
+            let (nth, (start, level, _)) = headings
+                .iter()
+                .enumerate()
+                .find(|(_, (_, _, text))| text.eq_ignore_ascii_case(title.trim()))?;
+            // The section goes on until a heading that is not nested in it
+            let end = headings[nth + 1..]
+                .iter()
+                .find(|(_, other, _)| other <= level)
+                .map_or(lines.len(), |(idx, _, _)| *idx);
+            (*start, end)
+        }
+        None => (0, lines.len()),
+    };
+
+    blocks(lines)
+        .into_iter()
+        .filter(|block| block.start > first && block.start <= last)
+        .nth(number.checked_sub(1)?)
+}
+
+/// Fenced code blocks of `lines`. Blocks that are not closed go on until the
+/// end of the file.
+fn blocks(lines: &[String]) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut idx = 0;
+    while idx < lines.len() {
+        let Some(open) = FENCE.captures(&lines[idx]) else {
+            idx += 1;
+            continue;
+        };
+
+        // Closed by a fence of the same character, at least as long
+        let fence = &open[2];
+        let close = (idx + 1..lines.len()).find(|close| {
+            FENCE.captures(&lines[*close]).is_some_and(|cap| {
+                cap[3].is_empty()
+                    && cap[2].starts_with(fence)
+                    && cap[2].chars().all(|c| fence.starts_with(c))
+            })
+        });
+
+        let end = close.unwrap_or(lines.len());
+        blocks.push(Block {
+            start: idx + 1,
+            end: end.saturating_sub(1).max(idx),
+            language: info_language(&open[3]),
+        });
+        idx = end + 1;
+    }
+
+    blocks
+}
+
+/// Lines, levels and titles of the headings of `lines` that are not in code
+/// blocks.
+fn headings(lines: &[String]) -> Vec<(usize, usize, String)> {
+    let fenced: Vec<(usize, usize)> = blocks(lines)
+        .iter()
+        .map(|block| (block.start - 1, block.end + 1))
+        .collect();
+
+    lines
+        .iter()
+        .enumerate()
+        .filter(|(idx, _)| {
+            !fenced
+                .iter()
+                .any(|(open, close)| open <= idx && idx <= close)
+        })
+        .filter_map(|(idx, line)| {
+            let cap = HEADING.captures(line)?;
+            let title = cap.get(2).map_or("", |title| title.as_str());
+            Some((idx, cap[1].len(), title.to_string()))
+        })
+        .collect()
+}
+
+/// Language named by the info string of a fence, like `rust`, `js title="a.js"`
+/// or `{python}`.
+fn info_language(info: &str) -> Language {
+    let name = info
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .next()
+        .unwrap_or_default()
+        .trim_matches(|c| c == '{' || c == '}' || c == '.');
+    if name.is_empty() {
+        Language::Unknown
+    } else {
+        Language::from_name(name)
+    }
+}
+
+/// A cell of a Jupyter notebook.
+#[derive(Debug, PartialEq)]
+pub struct Cell {
+    pub language: Language,
+    pub source: Vec<String>,
+}
+
+/// Cells of the Jupyter notebook `content`, `None` if it is not one. Code
+/// cells are in the language of the notebook's kernel, Python if it has none.
+pub fn notebook_cells(content: &str) -> Option<Vec<Cell>> {
+    let notebook: serde_json::Value = serde_json::from_str(content).ok()?;
+    let metadata = &notebook["metadata"];
+    let kernel = metadata["kernelspec"]["language"]
+        .as_str()
+        .or_else(|| metadata["language_info"]["name"].as_str())
+        .map_or(Language::Python, Language::from_name);
+
+    let cells = notebook["cells"].as_array()?;
+    let cells = cells
+        .iter()
+        .map(|cell| {
+            let language = match cell["cell_type"].as_str() {
+                Some("code") => kernel.clone(),
+                Some("markdown") => Language::Markdown,
+                _ => Language::Unknown,
+            };
+            // Sources are a string, or a list of lines that keep their newline
+            let source = match &cell["source"] {
+                serde_json::Value::Array(lines) => {
+                    lines.iter().filter_map(|line| line.as_str()).collect()
+                }
+                source => source.as_str().unwrap_or_default().to_string(),
+            };
+
+            Cell {
+                language,
+                source: source.lines().map(String::from).collect(),
+            }
+        })
+        .collect();
+
+    Some(cells)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_block, notebook_cells, Block, Cell, Language};
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn finds_blocks() {
+        let readme = lines(
+            "# App\n\
+             ```sh\n\
+             cargo install app\n\
+             ```\n\
+             ## Usage\n\
+             ````rust title=\"main.rs\"\n\
+             ```\n\
+             fn main() {}\n\
+             ````\n\
+             ### Options\n\
+             ~~~\n\
+             ~~~\n\
+             ## Usage notes\n\
+             ```{python}\n\
+             print(1)",
+        );
+
+        let block = |start, end, language| {
+            Some(Block {
+                start,
+                end,
+                language,
+            })
+        };
+        assert_eq!(find_block(&readme, 1, None), block(2, 2, Language::Bash));
+        assert_eq!(find_block(&readme, 2, None), block(6, 7, Language::Rust));
+        assert_eq!(
+            find_block(&readme, 4, None),
+            block(14, 14, Language::Python)
+        );
+        assert_eq!(find_block(&readme, 5, None), None);
+        assert_eq!(find_block(&readme, 0, None), None);
+
+        // Sections take their subsections, but not the headings after
+        assert_eq!(
+            find_block(&readme, 1, Some("usage")),
+            block(6, 7, Language::Rust)
+        );
+        assert_eq!(
+            find_block(&readme, 2, Some("Usage")),
+            block(11, 10, Language::Unknown)
+        );
+        assert_eq!(find_block(&readme, 3, Some("Usage")), None);
+        assert_eq!(find_block(&readme, 1, Some("Install")), None);
+    }
+
+    #[test]
+    fn reads_notebook_cells() {
+        let notebook = r##"{
+            "metadata": {"kernelspec": {"language": "R", "name": "ir"},
+                         "language_info": {"name": "ruby"}},
+            "cells": [
+                {"cell_type": "markdown", "source": "# Title\nText"},
+                {"cell_type": "code", "source": ["x = 1\n", "puts x"]}
+            ]
+        }"##;
+
+        let cells = notebook_cells(notebook).unwrap();
+        assert_eq!(
+            cells[0],
+            Cell {
+                language: Language::Markdown,
+                source: vec![String::from("# Title"), String::from("Text")],
+            }
+        );
+        // The kernel's language is not supported
+        assert_eq!(cells[1].language, Language::Unknown);
+        assert_eq!(cells[1].source, vec!["x = 1", "puts x"]);
+
+        let python = r#"{"metadata": {}, "cells": [{"cell_type": "code", "source": "1"}]}"#;
+        assert_eq!(
+            notebook_cells(python).unwrap()[0].language,
+            Language::Python
+        );
+        assert_eq!(notebook_cells(r#"{"name": "app"}"#), None);
+    }
+}
@@ -0,0 +1,209 @@
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::process::Command;
+
+use crate::bookmark::Bookmark;
+
+/// Line around the TOML front matter at the top of the buffer.
+const DELIMITER: &str = "+++";
+
+/// Metadata of the bookmark that can be edited along with its content.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FrontMatter {
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    urls: Vec<String>,
+    /// Markdown notes, last as they tend to span several lines
+    #[serde(default)]
+    notes: String,
+}
+
+/// Text the bookmark is edited as: its metadata as TOML front matter between
+/// `+++` lines, followed by its content.
+pub fn to_buffer(bookmark: &Bookmark) -> io::Result<String> {
+    let front_matter = FrontMatter {
+        description: bookmark.description.clone(),
+        tags: bookmark.tags.clone(),
+        urls: bookmark.urls.clone(),
+        notes: bookmark.notes.clone(),
+    };
+
+    // Notes are written as multi-line strings so they read like markdown
+    let mut buffer = format!("{}\n", DELIMITER);
+    let mut serializer = toml::Serializer::new(&mut buffer);
+    serializer.pretty_string(true).pretty_string_literal(false);
+    front_matter
+        .serialize(&mut serializer)
+        .map_err(io::Error::other)?;
+
+    buffer.push_str(DELIMITER);
+    buffer.push('\n');
+    buffer.push_str(&bookmark.content.join("\n"));
+    Ok(buffer)
+}
+
+/// Applies an edited buffer to `bookmark`. A new version is recorded if the
+/// content changed. Without front matter the metadata is left as it was.
+pub fn apply_buffer(bookmark: &mut Bookmark, buffer: &str) -> io::Result<()> {
+    let mut lines = buffer.lines().peekable();
+
+    if lines.peek() == Some(&DELIMITER) {
+        lines.next();
+        let front_matter: Vec<&str> = lines
+            .by_ref()
+            .take_while(|line| *line != DELIMITER)
+            .collect();
+        let front_matter: FrontMatter = toml::from_str(&front_matter.join("\n")).map_err(|e| {
+            let err_msg = format!("Invalid front matter: {}", e);
+            io::Error::new(io::ErrorKind::InvalidData, err_msg)
+        })?;
+
+        bookmark.description = front_matter.description;
+        bookmark.tags = front_matter.tags;
+        bookmark.urls = front_matter.urls;
+        bookmark.notes = front_matter.notes;
+    }
+
+    let content: Vec<String> = lines.map(String::from).collect();
+    bookmark.set_content(&content);
+    Ok(())
+}
+
+/// Command to edit text with: `$VISUAL`, `$EDITOR` or `vi`, in that order.
+fn editor_command() -> String {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|command| !command.trim().is_empty())
+        .unwrap_or_else(|| String::from("vi"))
+}
+
+/// Opens the bookmark in the user's editor and returns it as it was left.
+pub fn edit(bookmark: &Bookmark) -> io::Result<Bookmark> {
+    edit_with(bookmark, &editor_command())
+}
+
+fn edit_with(bookmark: &Bookmark, editor: &str) -> io::Result<Bookmark> {
+    // Created only readable by the user, keeping the extension so the editor
+    // highlights the content
+    let mut file = tempfile::Builder::new()
+        .prefix(&format!("capture-{}-", bookmark.name.replace('/', "-")))
+        .suffix(&format!(".{}", bookmark.lang.to_extension()))
+        .tempfile()?;
+    file.write_all(to_buffer(bookmark)?.as_bytes())?;
+    file.flush()?;
+
+    // Through the shell, like git does, so that the editor can be a quoted
+    // path or have arguments
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$@\"", editor))
+        .arg(editor)
+        .arg(file.path())
+        .status();
+
+    // Editors may replace the file rather than write to it
+    let buffer = fs::read_to_string(file.path());
+
+    let status = status?;
+    if !status.success() {
+        let err_msg = format!("Editor exited with {}, bookmark left unchanged", status);
+        return Err(io::Error::other(err_msg));
+    }
+
+    let mut edited = bookmark.clone();
+    apply_buffer(&mut edited, &buffer?)?;
+    Ok(edited)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_buffer, edit_with, to_buffer};
+    use crate::bookmark::Bookmark;
+    use crate::language::Language;
+    use std::io;
+
+    fn bookmark() -> Bookmark {
+        let content = vec![String::from("fn one() {"), String::from("}")];
+        let mut bookmark = Bookmark::new("rust/one", &content, Language::Rust);
+        bookmark.tags = vec![String::from("example")];
+        // Pinned, so that bookmarks built a second apart still compare equal
+        bookmark.updated = 1_700_000_000;
+        bookmark
+    }
+
+    #[test]
+    fn round_trips_buffer() {
+        let original = bookmark();
+        let buffer = to_buffer(&original).unwrap();
+        assert!(buffer.starts_with(
+            "+++\ndescription = \"\"\ntags = [\"example\"]\nurls = []\nnotes = \"\"\n+++\n"
+        ));
+
+        let mut edited = original.clone();
+        apply_buffer(&mut edited, &buffer).unwrap();
+        assert_eq!(edited, original);
+    }
+
+    #[test]
+    fn applies_changes_as_new_version() {
+        let original = bookmark();
+        let buffer =
+            "+++\ndescription = \"Returns one\"\ntags = []\n+++\nfn one() -> u8 {\n    1\n}\n";
+
+        let mut edited = original.clone();
+        apply_buffer(&mut edited, buffer).unwrap();
+        assert_eq!(edited.description, "Returns one");
+        assert!(edited.tags.is_empty());
+        assert_eq!(edited.content, vec!["fn one() -> u8 {", "    1", "}"]);
+        assert_eq!(edited.history, vec![original.id]);
+        assert_eq!(edited.version(), 2);
+
+        // Without front matter only the content changes
+        apply_buffer(&mut edited, "fn one() {}").unwrap();
+        assert_eq!(edited.description, "Returns one");
+        assert_eq!(edited.content, vec!["fn one() {}"]);
+        assert_eq!(edited.version(), 3);
+    }
+
+    #[test]
+    fn edits_notes_as_markdown() {
+        let mut original = bookmark();
+        original.notes = String::from("# Usage\n\nCall `one()`\n");
+        original.urls = vec![String::from("https://example.com/issues/1")];
+
+        let buffer = to_buffer(&original).unwrap();
+        assert!(buffer.contains("notes = \"\"\"\n# Usage\n\nCall `one()`\n\"\"\"\n"));
+
+        let mut edited = bookmark();
+        apply_buffer(&mut edited, &buffer).unwrap();
+        assert_eq!(edited, original);
+    }
+
+    #[test]
+    fn rejects_invalid_front_matter() {
+        let mut edited = bookmark();
+        let err = apply_buffer(&mut edited, "+++\ntitle = \"one\"\n+++\nfn one() {}").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(edited, bookmark());
+    }
+
+    #[test]
+    fn edits_in_external_editor() {
+        let edited = edit_with(&bookmark(), "sed -i s/one/two/").unwrap();
+        assert_eq!(edited.content, vec!["fn two() {", "}"]);
+        assert_eq!(edited.version(), 2);
+
+        assert!(edit_with(&bookmark(), "false").is_err());
+
+        // Arguments of the editor may be quoted
+        let edited = edit_with(&bookmark(), "sed -i 's/one/one more/'").unwrap();
+        assert_eq!(edited.content, vec!["fn one more() {", "}"]);
+    }
+}
@@ -0,0 +1,247 @@
+use std::io;
+use std::path::Path;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Color, Style, ThemeSet};
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+
+use crate::color::Colors;
+use crate::language::Language;
+
+/// Theme used when neither `--theme` nor the config choose one.
+pub const DEFAULT_THEME: &str = "base16-ocean.dark";
+
+/// Highlights code with syntect's syntaxes and themes, along with the
+/// `.sublime-syntax` and `.tmTheme` files in the `syntaxes` and `themes`
+/// folders of the config directory.
+pub struct Highlighter {
+    syntaxes: SyntaxSet,
+    themes: ThemeSet,
+    theme: String,
+    colors: Colors,
+}
+
+impl Highlighter {
+    /// Loads the syntaxes and themes, failing if `theme` is not one of them.
+    pub fn new(config_dir: Option<&Path>, theme: &str, colors: Colors) -> io::Result<Self> {
+        let mut syntaxes = SyntaxSet::load_defaults_newlines();
+        let mut themes = ThemeSet::load_defaults();
+
+        if let Some(dir) = config_dir {
+            let syntaxes_dir = dir.join("syntaxes");
+            if syntaxes_dir.is_dir() {
+                let mut builder = syntaxes.into_builder();
+                builder.add_from_folder(&syntaxes_dir, true).map_err(|e| {
+                    let err_msg = format!(
+                        "Error loading syntaxes from {}: {}",
+                        syntaxes_dir.display(),
+                        e
+                    );
+                    io::Error::new(io::ErrorKind::InvalidData, err_msg)
+                })?;
+                syntaxes = builder.build();
+            }
+
+            let themes_dir = dir.join("themes");
+            if themes_dir.is_dir() {
+                themes.add_from_folder(&themes_dir).map_err(|e| {
+                    let err_msg =
+                        format!("Error loading themes from {}: {}", themes_dir.display(), e);
+                    io::Error::new(io::ErrorKind::InvalidData, err_msg)
+                })?;
+            }
+        }
+
+        if !themes.themes.contains_key(theme) {
+            let err_msg = format!("Unknown theme '{}', see `capture themes`", theme);
+            return Err(io::Error::new(io::ErrorKind::NotFound, err_msg));
+        }
+
+        Ok(Highlighter {
+            syntaxes,
+            themes,
+            theme: theme.to_string(),
+            colors,
+        })
+    }
+
+    /// Names of every theme, sorted.
+    pub fn themes(&self) -> Vec<&str> {
+        self.themes.themes.keys().map(String::as_str).collect()
+    }
+
+    pub fn theme(&self) -> &str {
+        &self.theme
+    }
+
+    /// Lines of `content` escaped with the colors of the theme. Languages
+    /// without a syntax are left as plain text.
+    pub fn highlight(&self, lang: &Language, content: &[String]) -> Vec<String> {
+        if self.colors == Colors::None {
+            return content.to_vec();
+        }
+
+        self.styles(lang, content)
+            .iter()
+            .map(|ranges| {
+                let ranges: Vec<(Style, &str)> = ranges
+                    .iter()
+                    .map(|(style, text)| (*style, text.as_str()))
+                    .collect();
+                self.colors.escape(&ranges)
+            })
+            .collect()
+    }
+
+    /// Syntax of `lang`, by its extension or otherwise by the name in its
+    /// definition, including the user's syntaxes.
+    fn syntax(&self, lang: &Language) -> Option<&SyntaxReference> {
+        self.syntaxes
+            .find_syntax_by_extension(&lang.to_extension())
+            .or_else(|| {
+                lang.get_syntax()
+                    .and_then(|name| self.syntaxes.find_syntax_by_name(&name))
+            })
+    }
+
+    /// Scope of the syntax of `lang`, like `source.rust`, as editors name it.
+    pub fn scope(&self, lang: &Language) -> Option<String> {
+        self.syntax(lang).map(|syntax| syntax.scope.to_string())
+    }
+
+    /// Pieces of each line of `content` with the style the theme gives them.
+    pub fn styles(&self, lang: &Language, content: &[String]) -> Vec<Vec<(Style, String)>> {
+        let syntax = self
+            .syntax(lang)
+            .unwrap_or_else(|| self.syntaxes.find_syntax_plain_text());
+        let mut h = HighlightLines::new(syntax, &self.themes.themes[&self.theme]);
+
+        content
+            .iter()
+            .map(|line| {
+                // Syntaxes are loaded to match lines that end with a newline
+                let line = format!("{}\n", line);
+                match h.highlight_line(&line, &self.syntaxes) {
+                    Ok(ranges) => ranges
+                        .into_iter()
+                        .map(|(style, text)| (style, text.trim_end_matches('\n').to_string()))
+                        .filter(|(_, text)| !text.is_empty())
+                        .collect(),
+                    Err(_) => vec![(Style::default(), line.trim_end_matches('\n').to_string())],
+                }
+            })
+            .collect()
+    }
+
+    /// Background and foreground colors of the theme.
+    pub fn theme_colors(&self) -> (Color, Color) {
+        let settings = &self.themes.themes[&self.theme].settings;
+        (
+            settings.background.unwrap_or(Color::WHITE),
+            settings.foreground.unwrap_or(Color::BLACK),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Highlighter, DEFAULT_THEME};
+    use crate::color::Colors;
+    use crate::language::Language;
+    use std::fs;
+    use std::io;
+
+    fn content() -> Vec<String> {
+        vec![String::from("let one: number = 1;")]
+    }
+
+    #[test]
+    fn highlights_with_theme() {
+        let highlighter = Highlighter::new(None, DEFAULT_THEME, Colors::TrueColor).unwrap();
+        assert!(highlighter.themes().contains(&"InspiredGitHub"));
+
+        let lines = highlighter.highlight(&Language::Rust, &content());
+        assert!(lines[0].starts_with("\x1b[38;2;"));
+        assert!(lines[0].ends_with("\x1b[0m"));
+
+        let plain = Highlighter::new(None, DEFAULT_THEME, Colors::None).unwrap();
+        assert_eq!(plain.highlight(&Language::Rust, &content()), content());
+
+        let err = Highlighter::new(None, "missing", Colors::TrueColor)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn highlights_languages_without_syntax() {
+        let highlighter = Highlighter::new(None, DEFAULT_THEME, Colors::TrueColor).unwrap();
+        let lines = highlighter.highlight(&Language::Typescript, &content());
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].contains("let one: number = 1;"));
+
+        let lines = highlighter.highlight(&Language::Unknown, &content());
+        assert!(lines[0].contains("let one: number = 1;"));
+
+        // JSX is highlighted as JavaScript
+        assert_eq!(
+            highlighter.highlight(&Language::Jsx, &content()),
+            highlighter.highlight(&Language::Javascript, &content())
+        );
+    }
+
+    #[test]
+    fn loads_user_syntaxes_and_themes() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("syntaxes")).unwrap();
+        fs::write(
+            dir.path().join("syntaxes/TypeScript.sublime-syntax"),
+            "%YAML 1.2\n---\nname: TypeScript\nfile_extensions: [ts]\nscope: source.ts\n\
+             contexts:\n  main:\n    - match: '\\blet\\b'\n      scope: keyword.declaration.ts\n",
+        )
+        .unwrap();
+        fs::create_dir(dir.path().join("themes")).unwrap();
+        fs::write(dir.path().join("themes/Mine.tmTheme"), THEME).unwrap();
+
+        let highlighter = Highlighter::new(Some(dir.path()), "Mine", Colors::TrueColor).unwrap();
+        assert_eq!(highlighter.theme(), "Mine");
+
+        // The keyword gets the theme's color, the rest its foreground
+        let lines = highlighter.highlight(&Language::Typescript, &content());
+        assert!(lines[0].starts_with("\x1b[38;2;255;0;0mlet\x1b[38;2;0;0;0m"));
+        assert_eq!(
+            highlighter.scope(&Language::Typescript).as_deref(),
+            Some("source.ts")
+        );
+    }
+
+    const THEME: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>name</key>
+    <string>Mine</string>
+    <key>settings</key>
+    <array>
+        <dict>
+            <key>settings</key>
+            <dict>
+                <key>foreground</key>
+                <string>#000000</string>
+                <key>background</key>
+                <string>#FFFFFF</string>
+            </dict>
+        </dict>
+        <dict>
+            <key>scope</key>
+            <string>keyword</string>
+            <key>settings</key>
+            <dict>
+                <key>foreground</key>
+                <string>#FF0000</string>
+            </dict>
+        </dict>
+    </array>
+</dict>
+</plist>
+"#;
+}
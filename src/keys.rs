@@ -0,0 +1,475 @@
+use crate::rules::indentation;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::HashMap;
+
+lazy_static! {
+    static ref YAML_KEY: Regex =
+        Regex::new(r#"^\s*(?:"((?:[^"\\]|\\.)*)"|'([^']*)'|([^\s#'"{\[\-][^#]*?))\s*:(?:\s|$)"#)
+            .unwrap();
+    static ref TOML_HEADER: Regex =
+        Regex::new(r"^\s*(\[\[?)\s*([^\[\]]+?)\s*\]\]?\s*(?:#.*)?$").unwrap();
+}
+
+/// Lines where the YAML value at the dotted `path` starts, with its key,
+/// and ends. Items of sequences are named by their index, like `steps.0`.
+pub fn yaml(lines: &[String], path: &str) -> Option<(usize, usize)> {
+    let mut lines = lines.to_vec();
+    let (mut first, mut last) = (0, lines.len());
+    let mut bounds = None;
+    for segment in path.split('.') {
+        let (start, end) = yaml_entries(&lines, first, last)
+            .into_iter()
+            .find(|(name, _, _)| name == segment)
+            .map(|(_, start, end)| (start, end))?;
+        bounds = Some((start, end));
+
+        // What an item holds starts on its own line, right after the dash
+        if is_yaml_item(&lines[start]) {
+            let dash = indentation(&lines[start]);
+            lines[start].replace_range(dash..dash + 1, " ");
+            first = start;
+        } else {
+            first = start + 1;
+        }
+        last = end + 1;
+    }
+
+    bounds
+}
+
+/// Entries of the mapping or sequence in `lines[first..last]`, with their
+/// first and last lines. Comments and blank lines after an entry are left out.
+fn yaml_entries(lines: &[String], first: usize, last: usize) -> Vec<(String, usize, usize)> {
+    let content: Vec<usize> = (first..last)
+        .filter(|idx| is_yaml_content(&lines[*idx]))
+        .collect();
+    let Some(&head) = content.first() else {
+        return Vec::new();
+    };
+
+    let indent = indentation(&lines[head]);
+    let sequence = is_yaml_item(&lines[head]);
+    let starts: Vec<(String, usize)> = content
+        .iter()
+        .filter(|idx| indentation(&lines[**idx]) == indent)
+        .filter(|idx| is_yaml_item(&lines[**idx]) == sequence)
+        .filter_map(|idx| {
+            if sequence {
+                Some((String::new(), *idx))
+            } else {
+                yaml_key(&lines[*idx]).map(|key| (key, *idx))
+            }
+        })
+        .enumerate()
+        .map(|(nth, (key, idx))| {
+            if sequence {
+                (nth.to_string(), idx)
+            } else {
+                (key, idx)
+            }
+        })
+        .collect();
+
+    starts
+        .iter()
+        .enumerate()
+        .map(|(nth, (name, start))| {
+            let next = starts.get(nth + 1).map_or(last, |(_, next)| *next);
+            let end = content
+                .iter()
+                .rev()
+                .find(|idx| **idx < next)
+                .copied()
+                .unwrap_or(*start);
+            (name.clone(), *start, end)
+        })
+        .collect()
+}
+
+fn yaml_key(line: &str) -> Option<String> {
+    let cap = YAML_KEY.captures(line)?;
+    cap.iter()
+        .skip(1)
+        .flatten()
+        .next()
+        .map(|key| key.as_str().to_string())
+}
+
+fn is_yaml_item(line: &str) -> bool {
+    let line = line.trim();
+    line == "-" || line.starts_with("- ")
+}
+
+/// Whether `line` is more than a comment, a blank line or a document marker.
+fn is_yaml_content(line: &str) -> bool {
+    let line = line.trim();
+    !line.is_empty() && !line.starts_with('#') && line != "---" && line != "..."
+}
+
+/// Lines where the TOML value at the dotted `path` starts and ends. Tables
+/// take the keys and tables under them, and arrays of tables are named by
+/// their index, like `servers.1`.
+pub fn toml(lines: &[String], path: &str) -> Option<(usize, usize)> {
+    let mut items: Vec<(String, usize, usize)> = Vec::new();
+    let mut table = String::new();
+    let mut arrays: HashMap<String, usize> = HashMap::new();
+
+    let mut idx = 0;
+    while idx < lines.len() {
+        let line = lines[idx].trim();
+        if line.is_empty() || line.starts_with('#') {
+            idx += 1;
+            continue;
+        }
+
+        if let Some(cap) = TOML_HEADER.captures(line) {
+            let name = toml_path(&cap[2]);
+            table = if &cap[1] == "[[" {
+                let count = arrays.entry(name.clone()).or_insert(0);
+                *count += 1;
+                format!("{}.{}", name, *count - 1)
+            } else {
+                name
+            };
+            items.push((table.clone(), idx, idx));
+        } else if let Some((key, value)) = line.split_once('=') {
+            let key = match table.as_str() {
+                "" => toml_path(key),
+                table => format!("{}.{}", table, toml_path(key)),
+            };
+            let end = toml_value_end(lines, idx, value);
+            items.push((key, idx, end));
+            idx = end;
+        }
+        idx += 1;
+    }
+
+    let prefix = format!("{}.", path);
+    let matching: Vec<&(String, usize, usize)> = items
+        .iter()
+        .filter(|(name, _, _)| name == path || name.starts_with(&prefix))
+        .collect();
+    let start = matching.iter().map(|(_, start, _)| *start).min()?;
+    let end = matching.iter().map(|(_, _, end)| *end).max()?;
+    Some((start, end))
+}
+
+/// Dotted path of a key or table name, without the quotes and spaces.
+fn toml_path(key: &str) -> String {
+    key.split('.')
+        .map(|segment| segment.trim().trim_matches(|c| c == '"' || c == '\''))
+        .collect::<Vec<&str>>()
+        .join(".")
+}
+
+/// Line where the value starting on the line at `start` with `value` ends.
+/// Arrays, inline tables and multi-line strings can span lines.
+fn toml_value_end(lines: &[String], start: usize, value: &str) -> usize {
+    let mut depth = 0;
+    let mut multiline: Option<&str> = None;
+    let mut text = value;
+    let mut idx = start;
+    loop {
+        let mut pos = 0;
+        while pos < text.len() {
+            let rest = &text[pos..];
+            if let Some(close) = multiline {
+                match rest.find(close) {
+                    Some(offset) => {
+                        pos += offset + close.len();
+                        multiline = None;
+                    }
+                    None => break,
+                }
+                continue;
+            }
+
+            if rest.starts_with("\"\"\"") || rest.starts_with("'''") {
+                multiline = Some(&rest[..3]);
+                pos += 3;
+                continue;
+            }
+            match rest.as_bytes()[0] {
+                quote @ (b'"' | b'\'') => pos += string_len(rest.as_bytes(), quote),
+                b'#' => break,
+                b'[' | b'{' => {
+                    depth += 1;
+                    pos += 1;
+                }
+                b']' | b'}' => {
+                    depth -= 1;
+                    pos += 1;
+                }
+                _ => pos += rest.chars().next().map_or(1, char::len_utf8),
+            }
+        }
+
+        if (depth <= 0 && multiline.is_none()) || idx + 1 >= lines.len() {
+            return idx;
+        }
+        idx += 1;
+        text = &lines[idx];
+    }
+}
+
+/// Length of the string at the start of `text` up to its closing `quote`, or
+/// of the rest of the line if it is not closed. Only double quotes escape.
+fn string_len(text: &[u8], quote: u8) -> usize {
+    let mut pos = 1;
+    while pos < text.len() {
+        match text[pos] {
+            b'\\' if quote == b'"' => pos += 2,
+            byte if byte == quote => return pos + 1,
+            _ => pos += 1,
+        }
+    }
+
+    text.len()
+}
+
+/// Lines where the JSON value at the dotted `path` starts, with its key,
+/// and ends. Items of arrays are named by their index, like `servers.0`.
+pub fn json(lines: &[String], path: &str) -> Option<(usize, usize)> {
+    let text = lines.join("\n");
+    let path: Vec<&str> = path.split('.').collect();
+    let mut parser = Json {
+        text: text.as_bytes(),
+        pos: 0,
+        line: 0,
+        found: None,
+    };
+
+    parser.value(Some(&path), 0)?;
+    parser.found
+}
+
+/// Parser that skips over JSON and notes where the value looked for is.
+struct Json<'a> {
+    text: &'a [u8],
+    pos: usize,
+    line: usize,
+    found: Option<(usize, usize)>,
+}
+
+impl Json<'_> {
+    /// Skips the value at the cursor, which starts on the line `start` with
+    /// its key. `path` is what is left of the path to the value looked for,
+    /// `None` when it is not in this value. `None` if the JSON is not valid.
+    fn value(&mut self, path: Option<&[&str]>, start: usize) -> Option<()> {
+        self.skip_whitespace();
+        // The value looked for is this one, not one nested in it
+        let inner = path.filter(|path| !path.is_empty());
+        match *self.text.get(self.pos)? {
+            b'{' => {
+                self.advance();
+                self.skip_whitespace();
+                while self.peek()? != b'}' {
+                    let key_line = self.line;
+                    let key = self.string()?;
+                    self.skip_whitespace();
+                    if self.peek()? != b':' {
+                        return None;
+                    }
+                    self.advance();
+                    self.value(child(inner, &key), key_line)?;
+                    if !self.separator(b'}')? {
+                        break;
+                    }
+                }
+                self.advance();
+            }
+            b'[' => {
+                self.advance();
+                self.skip_whitespace();
+                let mut index = 0;
+                while self.peek()? != b']' {
+                    self.skip_whitespace();
+                    let item_line = self.line;
+                    self.value(child(inner, &index.to_string()), item_line)?;
+                    index += 1;
+                    if !self.separator(b']')? {
+                        break;
+                    }
+                }
+                self.advance();
+            }
+            b'"' => {
+                self.string()?;
+            }
+            _ => {
+                // Numbers, `true`, `false` and `null`
+                let literal = self.pos;
+                while self
+                    .peek()
+                    .is_some_and(|byte| !b",]}".contains(&byte) && !byte.is_ascii_whitespace())
+                {
+                    self.advance();
+                }
+                if self.pos == literal {
+                    return None;
+                }
+            }
+        }
+
+        if path.is_some_and(|path| path.is_empty()) {
+            self.found = Some((start, self.line));
+        }
+        Some(())
+    }
+
+    /// Skips the `,` between members or items, `false` if `close` comes
+    /// instead.
+    fn separator(&mut self, close: u8) -> Option<bool> {
+        self.skip_whitespace();
+        match self.peek()? {
+            b',' => {
+                self.advance();
+                self.skip_whitespace();
+                Some(true)
+            }
+            byte if byte == close => Some(false),
+            _ => None,
+        }
+    }
+
+    /// The string at the cursor, escapes left as they are.
+    fn string(&mut self) -> Option<String> {
+        if self.peek()? != b'"' {
+            return None;
+        }
+
+        let len = string_len(&self.text[self.pos..], b'"');
+        if len < 2 || self.text[self.pos + len - 1] != b'"' {
+            return None;
+        }
+        let string = String::from_utf8_lossy(&self.text[self.pos + 1..self.pos + len - 1]);
+        self.pos += len;
+        Some(string.into_owned())
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|byte| byte.is_ascii_whitespace()) {
+            self.advance();
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.pos).copied()
+    }
+
+    fn advance(&mut self) {
+        if self.peek() == Some(b'\n') {
+            self.line += 1;
+        }
+        self.pos += 1;
+    }
+}
+
+/// What is left of `path` inside the member or item `name`.
+fn child<'a>(path: Option<&'a [&'a str]>, name: &str) -> Option<&'a [&'a str]> {
+    path.and_then(|path| path.split_first())
+        .filter(|(head, _)| **head == name)
+        .map(|(_, tail)| tail)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{json, toml, yaml};
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn finds_yaml_keys() {
+        let config = lines(
+            "version: '3'\n\
+             services:\n\
+             \x20 # The backend\n\
+             \x20 api:\n\
+             \x20   image: api:latest\n\
+             \x20   ports:\n\
+             \x20   - 8080\n\
+             \x20   - 9090\n\
+             \n\
+             \x20 \"db\":\n\
+             \x20   image: postgres\n\
+             steps:\n\
+             - name: build\n\
+             \x20 run: make\n\
+             - name: test\n",
+        );
+
+        assert_eq!(yaml(&config, "version"), Some((0, 0)));
+        assert_eq!(yaml(&config, "services"), Some((1, 10)));
+        assert_eq!(yaml(&config, "services.api"), Some((3, 7)));
+        assert_eq!(yaml(&config, "services.api.ports.1"), Some((7, 7)));
+        assert_eq!(yaml(&config, "services.db.image"), Some((10, 10)));
+        assert_eq!(yaml(&config, "steps.0"), Some((12, 13)));
+        assert_eq!(yaml(&config, "steps.0.run"), Some((13, 13)));
+        assert_eq!(yaml(&config, "steps.1.name"), Some((14, 14)));
+        assert_eq!(yaml(&config, "services.web"), None);
+        assert_eq!(yaml(&config, "version.major"), None);
+    }
+
+    #[test]
+    fn finds_toml_keys() {
+        let manifest = lines(
+            "name = \"capture\"\n\
+             authors = [\n\
+             \x20 \"Ada\", # lead\n\
+             \x20 \"Grace]\",\n\
+             ]\n\
+             \n\
+             [dependencies]\n\
+             regex = \"1\"\n\
+             clap = { version = \"3.2\", features = [\"derive\"] }\n\
+             \n\
+             [dependencies.serde]\n\
+             version = \"1\"\n\
+             \n\
+             [[bin]]\n\
+             name = \"one\"\n\
+             [[bin]]\n\
+             name = \"two\"\n\
+             description = \"\"\"\n\
+             Second [binary\n\
+             \"\"\"\n",
+        );
+
+        assert_eq!(toml(&manifest, "name"), Some((0, 0)));
+        assert_eq!(toml(&manifest, "authors"), Some((1, 4)));
+        assert_eq!(toml(&manifest, "dependencies"), Some((6, 11)));
+        assert_eq!(toml(&manifest, "dependencies.clap"), Some((8, 8)));
+        assert_eq!(toml(&manifest, "dependencies.serde"), Some((10, 11)));
+        assert_eq!(toml(&manifest, "bin"), Some((13, 19)));
+        assert_eq!(toml(&manifest, "bin.1"), Some((15, 19)));
+        assert_eq!(toml(&manifest, "bin.1.description"), Some((17, 19)));
+        assert_eq!(toml(&manifest, "dependencies.png"), None);
+    }
+
+    #[test]
+    fn finds_json_keys() {
+        let package = lines(
+            "{\n\
+             \x20 \"name\": \"app\",\n\
+             \x20 \"scripts\": {\n\
+             \x20   \"build\": \"tsc }\",\n\
+             \x20   \"test\": \"jest\"\n\
+             \x20 },\n\
+             \x20 \"files\": [\n\
+             \x20   \"dist\",\n\
+             \x20   { \"src\": true }\n\
+             \x20 ]\n\
+             }",
+        );
+
+        assert_eq!(json(&package, "name"), Some((1, 1)));
+        assert_eq!(json(&package, "scripts"), Some((2, 5)));
+        assert_eq!(json(&package, "scripts.test"), Some((4, 4)));
+        assert_eq!(json(&package, "files.1.src"), Some((8, 8)));
+        assert_eq!(json(&package, "files.2"), None);
+        assert_eq!(json(&lines("{\"name\": }"), "name"), None);
+    }
+}
@@ -1,70 +1,227 @@
-use regex;
+use crate::definitions::{self, Definition};
+use lazy_static::lazy_static;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
 
-#[derive(Debug, PartialEq)]
+lazy_static! {
+    // Vim modelines setting `ft`, `filetype` or `syntax`, and Emacs ones setting `mode`
+    static ref MODELINE: regex::Regex = regex::Regex::new(
+        r"(?:\b(?:vim?|ex):.*\b(?:ft|filetype|syntax|syn)=([\w+#.-]+)|-\*-\s*(?:.*\bmode:\s*)?([\w+#.-]+).*-\*-)",
+    )
+    .unwrap();
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Language {
     Rust,
     Python,
     Javascript,
     Typescript,
+    Jsx,
+    Tsx,
+    Vue,
+    Svelte,
     Golang,
     C,
+    Cpp,
+    Java,
+    Kotlin,
+    CSharp,
+    Ruby,
+    Lua,
+    Elixir,
+    Bash,
+    Makefile,
+    Dockerfile,
+    Sql,
+    Yaml,
+    Toml,
+    Json,
+    Markdown,
+    Haskell,
+    OCaml,
+    FSharp,
+    /// Defined in the `languages.toml` file of the config directory
+    Custom(String),
 
     Unknown,
 }
 
+/// Built-in languages, by the name of their definition.
+const BUILT_IN: &[(Language, &str)] = &[
+    (Language::Rust, "Rust"),
+    (Language::Python, "Python"),
+    (Language::Javascript, "Javascript"),
+    (Language::Typescript, "Typescript"),
+    (Language::Jsx, "Jsx"),
+    (Language::Tsx, "Tsx"),
+    (Language::Vue, "Vue"),
+    (Language::Svelte, "Svelte"),
+    (Language::Golang, "Golang"),
+    (Language::C, "C"),
+    (Language::Cpp, "Cpp"),
+    (Language::Java, "Java"),
+    (Language::Kotlin, "Kotlin"),
+    (Language::CSharp, "CSharp"),
+    (Language::Ruby, "Ruby"),
+    (Language::Lua, "Lua"),
+    (Language::Elixir, "Elixir"),
+    (Language::Bash, "Bash"),
+    (Language::Makefile, "Makefile"),
+    (Language::Dockerfile, "Dockerfile"),
+    (Language::Sql, "Sql"),
+    (Language::Yaml, "Yaml"),
+    (Language::Toml, "Toml"),
+    (Language::Json, "Json"),
+    (Language::Markdown, "Markdown"),
+    (Language::Haskell, "Haskell"),
+    (Language::OCaml, "OCaml"),
+    (Language::FSharp, "FSharp"),
+];
+
 impl Language {
+    /// Name of the definition of the language.
+    pub fn name(&self) -> &str {
+        match self {
+            Language::Custom(name) => name,
+            _ => BUILT_IN
+                .iter()
+                .find(|(language, _)| language == self)
+                .map_or("Unknown", |(_, name)| name),
+        }
+    }
+
+    /// Language `definition` defines, built-in if it has the name of one.
+    pub(crate) fn from_definition(definition: &Definition) -> Self {
+        BUILT_IN
+            .iter()
+            .find(|(_, name)| name.eq_ignore_ascii_case(&definition.name))
+            .map_or_else(
+                || Language::Custom(definition.name.clone()),
+                |(language, _)| language.clone(),
+            )
+    }
+
+    /// First language whose definition matches `predicate`.
+    fn find(predicate: impl Fn(&Definition) -> bool) -> Self {
+        definitions::find(predicate).map_or(Language::Unknown, |definition| {
+            Language::from_definition(&definition)
+        })
+    }
+
+    /// Definition of the language, `None` if it is unknown.
+    pub fn definition(&self) -> Option<Arc<Definition>> {
+        if *self == Language::Unknown {
+            return None;
+        }
+
+        definitions::find(|definition| definition.name.eq_ignore_ascii_case(self.name()))
+    }
+
     pub fn from_extension(extension: &str) -> Self {
-        match extension {
-            "rs" => Language::Rust,
-            "py" => Language::Python,
-            "js" => Language::Javascript,
-            "ts" => Language::Typescript,
-            "go" => Language::Golang,
-            "c" | "cpp" | "cc" | "h" | "hpp" => Language::C,
+        Language::find(|definition| definition.extensions.iter().any(|known| known == extension))
+    }
+
+    /// Language of files that are known by their name, like `Makefile`.
+    pub fn from_file_name(name: &str) -> Self {
+        Language::find(|definition| definition.matches_file_name(name))
+    }
+
+    /// Language called `name` by interpreters, editors or people, like `python3`,
+    /// `javascriptreact` or `c++`. Extensions are names too.
+    pub fn from_name(name: &str) -> Self {
+        let name = name.to_lowercase();
+        // Versioned interpreters, like `python3.12` or `ruby2.7`
+        let unversioned = name.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+        match Language::find(|definition| {
+            definition.name.eq_ignore_ascii_case(unversioned)
+                || definition
+                    .aliases
+                    .iter()
+                    .any(|alias| alias.eq_ignore_ascii_case(unversioned))
+        }) {
+            Language::Unknown => Language::from_extension(&name),
+            language => language,
+        }
+    }
+
+    /// Language of the interpreter a `#!` line runs, like `#!/usr/bin/env python3`.
+    pub fn from_shebang(line: &str) -> Self {
+        let Some(command) = line.strip_prefix("#!") else {
+            return Language::Unknown;
+        };
+
+        // `env` runs the first argument that is not an option or a variable
+        let mut words = command.split_whitespace();
+        let mut interpreter = words.next().unwrap_or_default();
+        if interpreter.rsplit('/').next() == Some("env") {
+            interpreter = words
+                .find(|word| !word.starts_with('-') && !word.contains('='))
+                .unwrap_or_default();
+        }
+
+        match interpreter.rsplit('/').next() {
+            Some(name) if !name.is_empty() => Language::from_name(name),
             _ => Language::Unknown,
         }
     }
 
-    pub fn to_extension(&self) -> &'static str {
-        match self {
-            Language::Rust => "rs",
-            Language::Python => "py",
-            Language::Javascript => "js",
-            Language::Typescript => "ts",
-            Language::Golang => "go",
-            Language::C => "cpp",
-            Language::Unknown => "",
+    /// Language a Vim modeline like `# vim: set ft=ruby:` or an Emacs one like
+    /// `-*- mode: python -*-` sets in `line`.
+    pub fn from_modeline(line: &str) -> Self {
+        match MODELINE.captures(line) {
+            Some(cap) => cap
+                .iter()
+                .skip(1)
+                .flatten()
+                .next()
+                .map_or(Language::Unknown, |name| Language::from_name(name.as_str())),
+            None => Language::Unknown,
         }
     }
 
-    pub fn get_function_syntax(&self) -> regex::Regex {
-        match self {
-            Language::Rust => regex::Regex::new(r"^ *(?:pub)? *fn *([a-zA-Z0-9_]+).*\(.*\) *(?:-> *[a-zA-Z0-9_]+ *)?\{? *$"),
-            Language::Python => regex::Regex::new(r"^ *def *([a-zA-Z0-9_]+) *\([.]*\) *: *$"),
-            Language::Javascript => regex::Regex::new(
-                r"^ *(?:function|const|let) *([a-zA-Z0-9_]+) *=? *\(.*\) *(?:: *[a-zA-Z0-9_]+)? *(?:=>)? *\{? *$",
-            ),
-            Language::Typescript => regex::Regex::new(r"^ *(?:function|const|let) *([a-zA-Z0-9_]+) *=? *\(.*\) *(?:: *[a-zA-Z0-9_]*)? *(?:=>)? *\{? *$"),
-            Language::Golang => regex::Regex::new(r"^ *func *([a-zA-Z0-9_]+) *\(.*\) *(?:.*)? *\{? *$"),
-            // In C++, when creating snippet of class function, you only need to input the 'function_name',
-            // not the complete 'Class::function_name'
-            Language::C => regex::Regex::new(r"^ *[a-zA-Z0-9_*& ]+(?: |::)([a-zA-Z0-9_]+)\(.*\) *\{? *$"),
-            _ => regex::Regex::new(r".*"),
+    /// Language of a file with `extension` and `content`. Languages sharing the
+    /// extension of another, like C++ with C headers, claim the files whose
+    /// content matches theirs.
+    pub fn from_extension_and_content(extension: &str, content: &str) -> Self {
+        match Language::find(|definition| {
+            definition
+                .shared
+                .as_ref()
+                .is_some_and(|shared| shared.matches(extension, content))
+        }) {
+            Language::Unknown => Language::from_extension(extension),
+            language => language,
         }
-        .unwrap()
     }
 
-    pub fn get_comment_delimiters(&self) -> (String, String, String) {
-        let comment_delimiters = match self {
-            Language::Rust
-            | Language::Javascript
-            | Language::Typescript
-            | Language::Golang
-            | Language::C => ("//", "/*", "*/"),
-            Language::Python => ("#", r#"""""#, r#"""""#),
-            _ => ("", "", ""),
-        };
+    /// Extension snippets in the language are saved with.
+    pub fn to_extension(&self) -> String {
+        self.definition()
+            .and_then(|definition| definition.extensions.first().cloned())
+            .unwrap_or_default()
+    }
 
-        (comment_delimiters.0.to_string(), comment_delimiters.1.to_string(), comment_delimiters.2.to_string())
+    /// Name of the syntax to highlight the language with, when there is none for its extension.
+    pub fn get_syntax(&self) -> Option<String> {
+        self.definition()?.syntax.clone()
+    }
+}
+
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Language {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match Language::from_name(name) {
+            Language::Unknown => Err(format!("Unknown language '{}'", name)),
+            language => Ok(language),
+        }
     }
 }
@@ -1,73 +1,192 @@
-use std::{io, path};
+use std::{fs, io, path};
 
 pub mod bookmark;
+pub mod clipboard;
+pub mod color;
+pub mod config;
+pub mod definitions;
+mod documents;
+pub mod editor;
+pub mod highlight;
+mod keys;
 mod language;
+pub mod pager;
+pub mod paste;
+pub mod render;
 mod rules;
+mod statements;
+pub mod store;
+pub mod template;
 mod utils;
+pub mod view;
 
 pub struct Capture {
     rule: rules::Rule,
     path_str: String,
+    /// Lines of the file, read once so that standard input can be captured
+    lines: Vec<String>,
+    /// Whether the language was given rather than detected, so that code
+    /// blocks and cells keep it instead of their own
+    lang_given: bool,
     pub result: Vec<String>,
+    /// Line of the file each line of `result` comes from
+    origin: Vec<usize>,
 }
 
 impl Capture {
-    pub fn new(path: &path::Path) -> Result<Self, String> {
-        let rule = match rules::Rule::new(&path) {
-            Some(rule) => rule,
-            None => {
-                let error_msg = format!("Error creating Rule for: {}", path.to_str().unwrap());
-                return Err(error_msg);
-            }
+    /// Capture from the file at `path`, or standard input if it is `-`. Its
+    /// language is detected from the file unless `lang` names one.
+    pub fn new(path: &path::Path, lang: Option<&str>) -> Result<Self, String> {
+        let path_str = String::from(path.to_str().unwrap());
+        let content = if path_str == "-" {
+            io::read_to_string(io::stdin())
+        } else {
+            fs::read_to_string(path)
+        };
+        let content = content.map_err(|e| format!("Error reading {}: {}", path_str, e))?;
+
+        let rule = match lang {
+            Some(lang) => rules::Rule::with_language(lang.parse()?),
+            None => rules::Rule::new(path, &content),
         };
 
         Ok(Capture {
             rule,
-            path_str: String::from(path.to_str().unwrap()),
+            path_str,
+            lines: content.lines().map(String::from).collect(),
+            lang_given: lang.is_some(),
             result: Vec::new(),
+            origin: Vec::new(),
         })
     }
 
     pub fn from_function(&mut self, name: &String, include_comments: bool) -> io::Result<()> {
         if !self.result.is_empty() {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
-                String::from(format!("Snippet has already been created")),
+            return Err(io::Error::other(
+                "Snippet has already been created".to_string(),
             ));
         }
 
-        let mut start_line: usize = 0;
-        let mut end_line: usize = 0;
+        let bounds = self.rule.find_function(&self.lines, name);
+        let not_found = format!("Function '{}' not found in {}", name, self.path_str);
+        self.capture_bounds(bounds, not_found, include_comments)
+    }
+
+    /// Captures the value at the dotted `path` of a YAML, TOML or JSON file.
+    pub fn from_key(&mut self, path: &str, include_comments: bool) -> io::Result<()> {
+        if !self.rule.has_keys() {
+            let err_msg = format!("{} has no keys, only YAML, TOML and JSON do", self.path_str);
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, err_msg));
+        }
+
+        let bounds = self.rule.find_key(&self.lines, path);
+        let not_found = format!("Key '{}' not found in {}", path, self.path_str);
+        self.capture_bounds(bounds, not_found, include_comments)
+    }
+
+    /// Captures the SQL statement `number`, starting at 1.
+    pub fn from_statement(&mut self, number: usize, include_comments: bool) -> io::Result<()> {
+        self.check_sql()?;
+        let bounds = self.rule.find_statement(&self.lines, number);
+        let not_found = format!("Statement {} not found in {}", number, self.path_str);
+        self.capture_bounds(bounds, not_found, include_comments)
+    }
+
+    /// Captures the statement creating the SQL view `name`.
+    pub fn from_view(&mut self, name: &str, include_comments: bool) -> io::Result<()> {
+        self.check_sql()?;
+        let bounds = self.rule.find_view(&self.lines, name);
+        let not_found = format!("View '{}' not found in {}", name, self.path_str);
+        self.capture_bounds(bounds, not_found, include_comments)
+    }
 
-        let mut num_delimiters: usize = 0;
+    /// Captures the fenced code block `number` of a Markdown file, starting
+    /// at 1, or of the section under `heading`. The snippet is in the
+    /// language of the block.
+    pub fn from_block(
+        &mut self,
+        number: usize,
+        heading: Option<&str>,
+        include_comments: bool,
+    ) -> io::Result<()> {
+        if !self.rule.has_fences() && !self.lang_given {
+            let err_msg = format!("{} is not Markdown", self.path_str);
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, err_msg));
+        }
 
-        let lines = utils::read_lines(&self.path_str)?;
-        for (idx, line) in lines.enumerate() {
-            let line = line?;
-            if self.rule.contains_function(&line, &name) {
-                start_line = idx + 1;
+        let block = match documents::find_block(&self.lines, number, heading) {
+            Some(block) => block,
+            None => {
+                let err_msg = match heading {
+                    Some(heading) => format!(
+                        "Block {} not found under '{}' in {}",
+                        number, heading, self.path_str
+                    ),
+                    None => format!("Block {} not found in {}", number, self.path_str),
+                };
+                return Err(io::Error::new(io::ErrorKind::NotFound, err_msg));
             }
+        };
 
-            // TODO: Revisit this implementation, and should test for programming languages
-            // that do not use an explicit delimiter such as python
-            if start_line != 0 && line.contains(&self.rule.delimiter.0) {
-                num_delimiters += 1;
-            } else if start_line != 0 && line.contains(&self.rule.delimiter.1) {
-                num_delimiters -= 1;
+        if !self.lang_given {
+            self.rule = rules::Rule::with_language(block.language);
+        }
+        self.from_interval(block.start + 1, block.end + 1, include_comments)
+    }
 
-                if num_delimiters == 0 {
-                    end_line = idx + 1;
-                    break;
-                }
+    /// Captures the cell `number` of a Jupyter notebook, starting at 1. The
+    /// lines of the snippet come from the lines of the cell.
+    pub fn from_cell(&mut self, number: usize, include_comments: bool) -> io::Result<()> {
+        let cells = match documents::notebook_cells(&self.lines.join("\n")) {
+            Some(cells) => cells,
+            None => {
+                let err_msg = format!("{} is not a Jupyter notebook", self.path_str);
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, err_msg));
             }
+        };
+
+        let cell = match number
+            .checked_sub(1)
+            .and_then(|idx| cells.into_iter().nth(idx))
+        {
+            Some(cell) => cell,
+            None => {
+                let err_msg = format!("Cell {} not found in {}", number, self.path_str);
+                return Err(io::Error::new(io::ErrorKind::NotFound, err_msg));
+            }
+        };
+
+        if !self.lang_given {
+            self.rule = rules::Rule::with_language(cell.language);
         }
+        self.lines = cell.source;
+        self.from_interval(1, self.lines.len(), include_comments)?;
+        // Lines of the cell are not lines of the notebook, which is JSON
+        self.origin.clear();
+        Ok(())
+    }
 
-        if start_line == 0 && end_line == 0 {
-            let err_msg = format!("Function '{}' not found in {}", name, self.path_str);
-            return Err(io::Error::new(io::ErrorKind::NotFound, err_msg));
+    fn check_sql(&self) -> io::Result<()> {
+        if !self.rule.has_statements() {
+            let err_msg = format!("{} is not SQL", self.path_str);
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, err_msg));
         }
 
-        self.from_interval(start_line, end_line, include_comments)
+        Ok(())
+    }
+
+    /// Captures the lines within `bounds`, as indexes, or fails with
+    /// `not_found` if there are none.
+    fn capture_bounds(
+        &mut self,
+        bounds: Option<(usize, usize)>,
+        not_found: String,
+        include_comments: bool,
+    ) -> io::Result<()> {
+        match bounds {
+            Some((start, end)) => self.from_interval(start + 1, end + 1, include_comments),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, not_found)),
+        }
     }
 
     pub fn from_interval(
@@ -77,40 +196,39 @@ impl Capture {
         include_comments: bool,
     ) -> io::Result<()> {
         if !self.result.is_empty() {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
-                String::from(format!("Snippet has already been created")),
+            return Err(io::Error::other(
+                "Snippet has already been created".to_string(),
             ));
         }
 
-        let lines = utils::read_lines(&self.path_str)?;
         let mut result_lines = Vec::new();
+        let mut origin = Vec::new();
 
         let mut min_leading_spaces = -1;
         let mut number_multiline_comment: usize = 0;
 
-        for (idx, line) in lines.enumerate() {
+        for (idx, line) in self.lines.iter().enumerate() {
             let line_number = idx + 1;
             if line_number < start || line_number > end {
                 continue;
             }
 
-            let line = line?;
-
             // Clean out comments if specified
             if !include_comments {
-                match self.rule.contains_comment(&line) {
+                match self.rule.contains_comment(line) {
                     Some(rules::CommentType::SingleLine)
                     | Some(rules::CommentType::MultiLineComplete) => continue,
                     Some(rules::CommentType::MultiLineStart) => {
                         number_multiline_comment += 1;
                         continue;
                     }
-                    Some(rules::CommentType::MultiLineEnd) => {
-                        number_multiline_comment -= 1;
+                    // Code may end like a block comment outside of one, like
+                    // `x + 1 (* bump *)`, or `t[a[1]]` and `[[long string]]` in Lua
+                    Some(rules::CommentType::MultiLineEnd) if number_multiline_comment > 0 => {
+                        number_multiline_comment = number_multiline_comment.saturating_sub(1);
                         continue;
                     }
-                    None => (),
+                    _ => (),
                 }
             }
 
@@ -143,28 +261,33 @@ impl Capture {
             }
 
             result_lines.push(line.clone());
+            origin.push(line_number);
         }
 
         // Clean leading spaces based on the minimum number of leading spaces
         // found on the result strings, to prevent unnecessary indentation.
         for mut line in result_lines {
             let mut num = 0;
-            while !line.is_empty()
-                && line.chars().next().unwrap() == ' '
-                && num < min_leading_spaces
-            {
+            while !line.is_empty() && line.starts_with(' ') && num < min_leading_spaces {
                 line.remove(0);
                 num += 1;
             }
 
             self.result.push(line);
         }
+        self.origin = origin;
 
         Ok(())
     }
 
-    pub fn bookmark(&self, name: &String) -> io::Result<()> {
-        bookmark::create(&name, &self.result, &self.rule.language)
+    pub fn bookmark(&self, name: &str) -> bookmark::Bookmark {
+        let mut bk = bookmark::Bookmark::new(name, &self.result, self.rule.language.clone());
+        // Snippets read from standard input have no source
+        if self.path_str != "-" {
+            bk.source = self.path_str.clone();
+        }
+        bk.origin = self.origin.clone();
+        bk
     }
 
     pub fn print(&self) {
@@ -173,3 +296,76 @@ impl Capture {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Capture;
+    use std::io::Write;
+
+    fn capture(extension: &str, content: &str) -> (tempfile::NamedTempFile, Capture) {
+        let mut file = tempfile::Builder::new()
+            .suffix(extension)
+            .tempfile()
+            .unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+        let capture = Capture::new(file.path(), None).unwrap();
+        (file, capture)
+    }
+
+    #[test]
+    fn keeps_code_ending_like_block_comments() {
+        let (_file, mut cap) = capture(
+            ".ml",
+            "let f x =\n\
+             \x20 (* Adds one\n\
+             \x20    to x *)\n\
+             \x20 let y = x + 1 (* bump *) in\n\
+             \x20 y\n",
+        );
+        cap.from_function(&String::from("f"), false).unwrap();
+        assert_eq!(
+            cap.result,
+            vec!["let f x =", "  let y = x + 1 (* bump *) in", "  y"]
+        );
+    }
+
+    #[test]
+    fn keeps_lua_code_ending_like_block_comments() {
+        let (_file, mut cap) = capture(
+            ".lua",
+            "local function f(t, a)\n\
+             \x20 --[[ Looks up\n\
+             \x20 the first ]]\n\
+             \x20 local s = [[hi]]\n\
+             \x20 return t[a[1]]\n\
+             end\n",
+        );
+        cap.from_function(&String::from("f"), false).unwrap();
+        assert_eq!(
+            cap.result,
+            vec![
+                "local function f(t, a)",
+                "  local s = [[hi]]",
+                "  return t[a[1]]",
+                "end"
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_no_origin_lines_of_cells() {
+        let (_file, mut cap) = capture(
+            ".ipynb",
+            r##"{
+                "metadata": {"kernelspec": {"language": "python"}},
+                "cells": [
+                    {"cell_type": "markdown", "source": ["# Title"]},
+                    {"cell_type": "code", "source": ["# Setup\n", "x = 1\n", "print(x)"]}
+                ]
+            }"##,
+        );
+        cap.from_cell(2, false).unwrap();
+        assert_eq!(cap.result, vec!["x = 1", "print(x)"]);
+        assert!(cap.bookmark("setup").origin.is_empty());
+    }
+}
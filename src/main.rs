@@ -1,5 +1,19 @@
-use capture;
+use capture::clipboard;
+use capture::color::{ColorMode, Colors};
+use capture::config::Config;
+use capture::definitions;
+use capture::editor;
+use capture::highlight::{self, Highlighter};
+use capture::pager;
+use capture::paste::{Anchor, Paste};
+use capture::render::{self, Format};
+use capture::store::{self, SnippetStore};
+use capture::template::{self, SnippetFormat, Template};
+use capture::view::{self, LineNumbers, SortKey, View};
 use clap::Parser;
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::num::NonZeroUsize;
 use std::path;
 
 #[derive(clap::Subcommand, Debug)]
@@ -13,6 +27,31 @@ enum CaptureType {
         #[clap(default_value = "0:0")]
         interval: String,
     },
+    Key {
+        /// Dotted path of the value in a YAML, TOML or JSON file, like services.api
+        path: String,
+    },
+    Statement {
+        /// Number of the SQL statement in the file, starting at 1
+        number: NonZeroUsize,
+    },
+    View {
+        /// Name of the SQL view to create bookmark from
+        name: String,
+    },
+    Block {
+        /// Number of the fenced code block in the Markdown file, starting at 1
+        #[clap(default_value = "1")]
+        number: NonZeroUsize,
+
+        /// Only count the blocks in the section under this heading
+        #[clap(long)]
+        heading: Option<String>,
+    },
+    Cell {
+        /// Number of the cell in the Jupyter notebook, starting at 1
+        number: NonZeroUsize,
+    },
 }
 
 #[derive(clap::Parser, Debug)]
@@ -20,14 +59,26 @@ struct AddCommand {
     /// Name of the new bookmark
     name: String,
 
-    /// File to create bookmark from
+    /// File to create bookmark from, or - for standard input
     #[clap(short, long, value_parser)]
     file: String,
 
+    /// Language of the code, like python or sh, when it cannot be told from the file
+    #[clap(long)]
+    lang: Option<String>,
+
     /// If the bookmark should include comments or skip them
     #[clap(long)]
     no_comments: bool,
 
+    /// Short description of what the code does
+    #[clap(long)]
+    description: Option<String>,
+
+    /// Related link, like the issue or pull request the code comes from
+    #[clap(long = "url")]
+    urls: Vec<String>,
+
     /// How to create the bookmark
     #[clap(subcommand)]
     action: CaptureType,
@@ -39,10 +90,138 @@ struct DeleteCommand {
     name: String,
 }
 
+#[derive(clap::Parser, Debug)]
+struct EditCommand {
+    /// Name of the bookmark to edit
+    name: String,
+}
+
+#[derive(clap::Parser, Debug)]
+struct RenameCommand {
+    /// Name of the bookmark to rename
+    name: String,
+
+    /// New name of the bookmark
+    new_name: String,
+}
+
+#[derive(clap::Parser, Debug)]
+struct CopyCommand {
+    /// Name of the bookmark to copy
+    name: String,
+
+    /// Name of the copy
+    new_name: String,
+}
+
+#[derive(clap::Parser, Debug)]
+struct MoveCommand {
+    /// Name of the bookmark to move
+    name: String,
+
+    /// New name of the bookmark, like namespace/name. Ending it with '/' moves
+    /// the bookmark into that namespace keeping its name
+    destination: String,
+}
+
 #[derive(clap::Parser, Debug)]
 struct GetCommand {
     /// Name of the bookmark to delete
     name: String,
+
+    /// Print the bookmark as JSON
+    #[clap(long)]
+    json: bool,
+
+    /// Fill in a placeholder of the bookmark, like struct_name=Foo. The
+    /// placeholders left are asked for when run from a terminal
+    #[clap(long = "set", value_parser = template::parse_assignment)]
+    values: Vec<(String, String)>,
+
+    /// Fill in the placeholders of the bookmark, asking for each of them
+    #[clap(long)]
+    render: bool,
+
+    /// Print nothing but the code, without highlighting
+    #[clap(long, conflicts_with = "json")]
+    raw: bool,
+
+    /// Show the number of each line
+    #[clap(short = 'n', long, conflicts_with_all = &["json", "raw"])]
+    line_numbers: bool,
+
+    /// Number lines as in the file the bookmark was captured from, marking
+    /// the lines left out of it
+    #[clap(long, conflicts_with_all = &["json", "raw"])]
+    origin: bool,
+
+    /// Show the name, language, tags and source of the bookmark in a box
+    #[clap(long, conflicts_with_all = &["json", "raw"])]
+    header: bool,
+
+    /// Also put the code on the clipboard, through $CAPTURE_COPY_COMMAND,
+    /// xclip, wl-copy or the terminal
+    #[clap(long)]
+    copy: bool,
+}
+
+#[derive(clap::Parser, Debug)]
+struct PasteCommand {
+    /// Name of the bookmark to paste
+    name: String,
+
+    /// File to paste the bookmark into
+    #[clap(long)]
+    into: String,
+
+    /// Line to paste the bookmark at, pushing that line down
+    #[clap(long, required_unless_present = "after-function")]
+    at: Option<usize>,
+
+    /// Name of the function to paste the bookmark after
+    #[clap(long, conflicts_with = "at")]
+    after_function: Option<String>,
+
+    /// Show the changes to the file instead of making them
+    #[clap(long)]
+    dry_run: bool,
+}
+
+#[derive(clap::Parser, Debug)]
+struct ExportCommand {
+    /// Name of the bookmark to export
+    name: String,
+
+    /// Snippet format to export to: vscode, sublime or ultisnips
+    #[clap(long, value_parser)]
+    format: SnippetFormat,
+
+    /// Text that triggers the snippet, the name of the bookmark without its
+    /// namespace by default
+    #[clap(long)]
+    prefix: Option<String>,
+}
+
+#[derive(clap::Parser, Debug)]
+struct RenderCommand {
+    /// Name of the bookmark to render
+    name: String,
+
+    /// Format to render to: html, md, svg or png
+    #[clap(long, value_parser)]
+    format: Format,
+
+    /// File to write to instead of standard output
+    #[clap(short, long)]
+    output: Option<path::PathBuf>,
+
+    /// Number the lines of the bookmark
+    #[clap(short = 'n', long)]
+    line_numbers: bool,
+
+    /// Add a title bar with the name of the bookmark
+    #[clap(long)]
+    title: bool,
 }
 
 #[derive(clap::Parser, Debug)]
@@ -50,18 +229,111 @@ struct ListCommand {
     /// Only display name and id of the bookmark
     #[clap(long)]
     oneline: bool,
+
+    /// Print the bookmarks as a JSON array
+    #[clap(long)]
+    json: bool,
+
+    /// Show one row per bookmark with its name, language, lines, tags and
+    /// update date
+    #[clap(long, conflicts_with_all = &["oneline", "json"])]
+    table: bool,
+
+    /// What to sort the bookmarks by: name, lang, lines or updated
+    #[clap(long, default_value = "name", value_parser)]
+    sort: SortKey,
+
+    /// Sort in descending order
+    #[clap(long)]
+    reverse: bool,
+
+    /// Number of bookmarks per page
+    #[clap(long)]
+    limit: Option<NonZeroUsize>,
+
+    /// Page of bookmarks to show, starting at 1
+    #[clap(long, requires = "limit")]
+    page: Option<usize>,
+}
+
+#[derive(clap::Parser, Debug)]
+struct SearchCommand {
+    /// Text to look for in the name, description, notes, tags, links and
+    /// content of the bookmarks
+    query: String,
+
+    /// Only display name and id of the bookmarks found
+    #[clap(long)]
+    oneline: bool,
+
+    /// Print the bookmarks found as a JSON array
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(clap::Parser, Debug)]
+struct InitCommand {
+    /// Keep bookmarks in a git repository that can be shared with sync
+    #[clap(long)]
+    git: bool,
+
+    /// Keep bookmarks and their content in a single sqlite file
+    #[clap(long, conflicts_with = "git")]
+    single_file: bool,
+
+    /// Url of the repository to sync the git-backed store with
+    #[clap(long, requires = "git")]
+    remote: Option<String>,
+}
+
+#[derive(clap::Parser, Debug)]
+struct SyncCommand {
+    /// Name of the git remote to sync bookmarks with
+    #[clap(long, default_value = "origin")]
+    remote: String,
+}
+
+#[derive(clap::Parser, Debug)]
+struct FsckCommand {
+    /// Fix the problems found instead of only reporting them
+    #[clap(long)]
+    repair: bool,
 }
 
 #[derive(clap::Subcommand, Debug)]
 enum Action {
+    /// Create the bookmark store in the current directory
+    Init(InitCommand),
     /// Create new bookmark
     Add(AddCommand),
     /// Delete an existing bookmark
     Delete(DeleteCommand),
+    /// Edit the content and metadata of a bookmark in $VISUAL or $EDITOR
+    Edit(EditCommand),
+    /// Give an existing bookmark a new name
+    Rename(RenameCommand),
+    /// Copy an existing bookmark under a new name
+    Cp(CopyCommand),
+    /// Move an existing bookmark to another namespace
+    Mv(MoveCommand),
     /// Get an existing bookmark
     Get(GetCommand),
+    /// Insert an existing bookmark into a file
+    Paste(PasteCommand),
+    /// Convert a bookmark to an editor snippet
+    Export(ExportCommand),
+    /// Render a bookmark to HTML, Markdown or an image
+    Render(RenderCommand),
     /// List all saved bookmarks
     List(ListCommand),
+    /// Find bookmarks containing some text
+    Search(SearchCommand),
+    /// Pull, merge and push bookmarks of a git-backed store
+    Sync(SyncCommand),
+    /// Check the bookmark store for orphaned, missing or modified content
+    Fsck(FsckCommand),
+    /// List the themes code can be highlighted with
+    Themes,
     //Snippet,
 }
 
@@ -70,14 +342,95 @@ enum Action {
 struct Args {
     #[clap(subcommand)]
     action: Action,
+
+    /// When to highlight code: auto, always or never. Auto highlights when
+    /// printing to a terminal and NO_COLOR is not set
+    #[clap(long, global = true, default_value = "auto", value_parser)]
+    color: ColorMode,
+
+    /// Theme to highlight code with, see `capture themes`. Defaults to the
+    /// theme in the config file
+    #[clap(long, global = true)]
+    theme: Option<String>,
+
+    /// Never page output longer than the terminal through $PAGER
+    #[clap(long, global = true)]
+    no_pager: bool,
+}
+
+/// Theme chosen with `--theme`, in the config or the default one, along with
+/// the config directory user themes and syntaxes are loaded from.
+fn theme_setting(theme: Option<&str>) -> std::io::Result<(Option<path::PathBuf>, String)> {
+    let dir = Config::dir();
+    let config = match &dir {
+        Some(dir) => Config::load(dir)?,
+        None => Config::default(),
+    };
+
+    let theme = theme
+        .map(String::from)
+        .or(config.theme)
+        .unwrap_or_else(|| String::from(highlight::DEFAULT_THEME));
+    Ok((dir, theme))
+}
+
+fn load_highlighter(color: ColorMode, theme: Option<&str>) -> Option<Highlighter> {
+    let highlighter = theme_setting(theme)
+        .and_then(|(dir, theme)| Highlighter::new(dir.as_deref(), &theme, Colors::detect(color)));
+
+    match highlighter {
+        Ok(highlighter) => Some(highlighter),
+        Err(e) => {
+            eprintln!("Error loading highlighting: {}", e);
+            None
+        }
+    }
+}
+
+fn themes_command(theme: Option<&str>) {
+    let highlighter = theme_setting(theme).and_then(|(dir, theme)| {
+        Highlighter::new(dir.as_deref(), highlight::DEFAULT_THEME, Colors::None)
+            .map(|highlighter| (highlighter, theme))
+    });
+
+    match highlighter {
+        Ok((highlighter, theme)) => {
+            for name in highlighter.themes() {
+                let marker = if name == theme { "*" } else { " " };
+                println!("{} {}", marker, name);
+            }
+        }
+        Err(e) => eprintln!("Error loading themes: {}", e),
+    }
 }
 
-fn add_command(command: &AddCommand) {
+fn init_command(command: &InitCommand) {
+    let backend = if command.git {
+        store::Backend::Git
+    } else if command.single_file {
+        store::Backend::Sqlite
+    } else {
+        store::Backend::Files
+    };
+
+    match store::setup(backend, command.remote.as_deref()) {
+        Ok(()) => println!("Successfully created bookmark store"),
+        Err(e) => eprintln!("Error creating bookmark store: {}", e),
+    }
+}
+
+fn add_command(store: &mut dyn SnippetStore, command: &AddCommand) {
     let path = path::Path::new(&command.file);
 
-    let mut cap = capture::Capture::new(&path).unwrap();
+    let mut cap = match capture::Capture::new(path, command.lang.as_deref()) {
+        Ok(cap) => cap,
+        Err(e) => {
+            eprintln!("Error creating bookmark: {}", e);
+            return;
+        }
+    };
     match &command.action {
-        CaptureType::Function { name } => match cap.from_function(&name, !command.no_comments) {
+        CaptureType::Function { name } => match cap.from_function(name, !command.no_comments) {
             Ok(()) => (),
             Err(e) => {
                 eprintln!("Error creating bookmark from function: {}", e);
@@ -101,43 +454,537 @@ fn add_command(command: &AddCommand) {
 
             cap.from_interval(start, end, !command.no_comments).unwrap();
         }
+        CaptureType::Key { path } => {
+            if let Err(e) = cap.from_key(path, !command.no_comments) {
+                eprintln!("Error creating bookmark from key: {}", e);
+                return;
+            }
+        }
+        CaptureType::Statement { number } => {
+            if let Err(e) = cap.from_statement(number.get(), !command.no_comments) {
+                eprintln!("Error creating bookmark from statement: {}", e);
+                return;
+            }
+        }
+        CaptureType::View { name } => {
+            if let Err(e) = cap.from_view(name, !command.no_comments) {
+                eprintln!("Error creating bookmark from view: {}", e);
+                return;
+            }
+        }
+        CaptureType::Block { number, heading } => {
+            let heading = heading.as_deref();
+            if let Err(e) = cap.from_block(number.get(), heading, !command.no_comments) {
+                eprintln!("Error creating bookmark from block: {}", e);
+                return;
+            }
+        }
+        CaptureType::Cell { number } => {
+            if let Err(e) = cap.from_cell(number.get(), !command.no_comments) {
+                eprintln!("Error creating bookmark from cell: {}", e);
+                return;
+            }
+        }
     }
 
-    match cap.bookmark(&command.name) {
+    let mut bookmark = cap.bookmark(&command.name);
+    bookmark.description = command.description.clone().unwrap_or_default();
+    bookmark.urls = command.urls.clone();
+
+    match store.create(&bookmark) {
         Ok(()) => println!("Successfully created bookmark: '{}'", command.name),
         Err(e) => eprintln!("Error creating bookmark: {}", e),
     };
 }
 
-fn delete_command(name: &String) {
-    match capture::bookmark::delete(&name) {
+fn delete_command(store: &mut dyn SnippetStore, name: &str) {
+    match store.delete(name) {
         Ok(()) => println!("Bookmark '{}' deleted successfully", name),
         Err(e) => eprintln!("Error deleting bookmark: {}", e),
     }
 }
 
-fn get_command(name: &String) {
-    let bk = capture::bookmark::get_bookmark(&name).unwrap();
-    match bk {
-        Some(bk) => bk.print(true),
-        None => eprintln!("Bookmark '{}' does not exist", name),
+fn edit_command(store: &mut dyn SnippetStore, name: &str) {
+    let bookmark = match store.get(name) {
+        Ok(Some(bookmark)) => bookmark,
+        Ok(None) => {
+            eprintln!("Bookmark '{}' does not exist", name);
+            return;
+        }
+        Err(e) => {
+            eprintln!("Error getting bookmark: {}", e);
+            return;
+        }
+    };
+
+    let mut edited = match editor::edit(&bookmark) {
+        Ok(edited) => edited,
+        Err(e) => {
+            eprintln!("Error editing bookmark: {}", e);
+            return;
+        }
+    };
+    if edited == bookmark {
+        println!("Bookmark '{}' was not changed", name);
+        return;
+    }
+    edited.touch();
+
+    match store.update(&edited) {
+        Ok(()) => println!("Bookmark '{}' saved as version {}", name, edited.version()),
+        Err(e) => eprintln!("Error saving bookmark: {}", e),
+    }
+}
+
+fn rename_command(store: &mut dyn SnippetStore, name: &str, new_name: &str) {
+    match store.rename(name, new_name) {
+        Ok(()) => println!("Bookmark '{}' renamed to '{}'", name, new_name),
+        Err(e) => eprintln!("Error renaming bookmark: {}", e),
+    }
+}
+
+fn copy_command(store: &mut dyn SnippetStore, command: &CopyCommand) {
+    let (name, new_name) = (&command.name, &command.new_name);
+    match store.copy(name, new_name) {
+        Ok(()) => println!("Bookmark '{}' copied to '{}'", name, new_name),
+        Err(e) => eprintln!("Error copying bookmark: {}", e),
+    }
+}
+
+fn move_command(store: &mut dyn SnippetStore, command: &MoveCommand) {
+    let name = &command.name;
+    let new_name = match command.destination.strip_suffix('/') {
+        Some(namespace) => {
+            let base_name = name.rsplit('/').next().unwrap_or(name);
+            format!("{}/{}", namespace, base_name)
+        }
+        None => command.destination.clone(),
+    };
+
+    match store.rename(name, &new_name) {
+        Ok(()) => println!("Bookmark '{}' moved to '{}'", name, new_name),
+        Err(e) => eprintln!("Error moving bookmark: {}", e),
+    }
+}
+
+fn get_command(
+    store: &dyn SnippetStore,
+    command: &GetCommand,
+    highlighter: &Highlighter,
+    paging: bool,
+) {
+    let name = &command.name;
+    let mut bk = match store.get(name) {
+        Ok(Some(bk)) => bk,
+        Ok(None) => {
+            eprintln!("Bookmark '{}' does not exist", name);
+            return;
+        }
+        Err(e) => {
+            eprintln!("Error getting bookmark: {}", e);
+            return;
+        }
+    };
+
+    if command.render || !command.values.is_empty() {
+        let values: BTreeMap<String, String> = command.values.iter().cloned().collect();
+        match render(&bk.content, values) {
+            Ok(content) => bk.content = content,
+            Err(e) => {
+                eprintln!("Error rendering bookmark: {}", e);
+                return;
+            }
+        }
+    }
+
+    let lines = if command.json {
+        json_lines(&bk.to_json())
+    } else if command.raw {
+        bk.content.clone()
+    } else {
+        let line_numbers = if command.origin {
+            if bk.origin.is_empty() {
+                eprintln!("Bookmark '{}' has no origin lines, numbering from 1", name);
+            }
+            Some(LineNumbers::Origin)
+        } else if command.line_numbers {
+            Some(LineNumbers::Relative)
+        } else {
+            None
+        };
+        let view = View {
+            line_numbers,
+            header: command.header,
+        };
+        bk.display(true, highlighter, &view)
+    };
+    if let Err(e) = pager::output(&lines, paging) {
+        eprintln!("Error printing bookmark: {}", e);
+    }
+
+    if command.copy {
+        match clipboard::copy(&bk.content.join("\n")) {
+            Ok(clipboard::Method::Command(command)) => {
+                eprintln!(
+                    "Bookmark '{}' copied to the clipboard with {}",
+                    name, command
+                )
+            }
+            Ok(clipboard::Method::Osc52) => {
+                eprintln!(
+                    "Bookmark '{}' copied to the clipboard through the terminal",
+                    name
+                )
+            }
+            Err(e) => eprintln!("Error copying bookmark: {}", e),
+        }
+    }
+}
+
+/// Fills in the placeholders of `content`, asking for the ones without a
+/// value in `values` when stdin is a terminal.
+fn render(content: &[String], mut values: BTreeMap<String, String>) -> io::Result<Vec<String>> {
+    let template = Template::parse(content);
+    template.check_tabstops()?;
+    let missing = template.missing(&values);
+
+    if template.unknown(&values).is_empty() && !missing.is_empty() && io::stdin().is_terminal() {
+        let mut lines = io::stdin().lock().lines();
+        for name in missing {
+            eprint!("{}: ", name);
+            io::stderr().flush()?;
+            match lines.next() {
+                Some(line) => values.insert(name, line?),
+                None => break,
+            };
+        }
+    }
+
+    template.render(&values)
+}
+
+fn paste_command(store: &dyn SnippetStore, command: &PasteCommand) {
+    let name = &command.name;
+    let bk = match store.get(name) {
+        Ok(Some(bk)) => bk,
+        Ok(None) => {
+            eprintln!("Bookmark '{}' does not exist", name);
+            return;
+        }
+        Err(e) => {
+            eprintln!("Error getting bookmark: {}", e);
+            return;
+        }
+    };
+
+    let anchor = match (&command.at, &command.after_function) {
+        (Some(line), _) => Anchor::Line(*line),
+        (None, Some(function)) => Anchor::AfterFunction(function.clone()),
+        (None, None) => unreachable!(),
+    };
+
+    let path = path::Path::new(&command.into);
+    let paste = match Paste::new(&bk, path, &anchor) {
+        Ok(paste) => paste,
+        Err(e) => {
+            eprintln!("Error pasting bookmark: {}", e);
+            return;
+        }
+    };
+
+    if command.dry_run {
+        print!("{}", paste.diff(path));
+        return;
+    }
+
+    match paste.write(path) {
+        Ok(()) => println!(
+            "Pasted bookmark '{}' into {} at line {}",
+            name, command.into, paste.line
+        ),
+        Err(e) => eprintln!("Error pasting bookmark: {}", e),
+    }
+}
+
+fn export_command(store: &dyn SnippetStore, command: &ExportCommand, highlighter: &Highlighter) {
+    let name = &command.name;
+    let bk = match store.get(name) {
+        Ok(Some(bk)) => bk,
+        Ok(None) => {
+            eprintln!("Bookmark '{}' does not exist", name);
+            return;
+        }
+        Err(e) => {
+            eprintln!("Error getting bookmark: {}", e);
+            return;
+        }
+    };
+
+    let base_name = name.rsplit('/').next().unwrap_or(name);
+    let prefix = command.prefix.as_deref().unwrap_or(base_name);
+    match template::export(&bk, command.format, prefix, highlighter) {
+        Ok(snippet) => println!("{}", snippet.trim_end()),
+        Err(e) => eprintln!("Error exporting bookmark: {}", e),
+    }
+}
+
+fn render_command(store: &dyn SnippetStore, command: &RenderCommand, theme: Option<&str>) {
+    let name = &command.name;
+    let bk = match store.get(name) {
+        Ok(Some(bk)) => bk,
+        Ok(None) => {
+            eprintln!("Bookmark '{}' does not exist", name);
+            return;
+        }
+        Err(e) => {
+            eprintln!("Error getting bookmark: {}", e);
+            return;
+        }
+    };
+
+    if command.format == Format::Png && command.output.is_none() && io::stdout().is_terminal() {
+        eprintln!("Refusing to write a PNG image to the terminal, use --output");
+        return;
+    }
+
+    // Rendered output is always in the colors of the theme
+    let highlighter = theme_setting(theme)
+        .and_then(|(dir, theme)| Highlighter::new(dir.as_deref(), &theme, Colors::TrueColor));
+    let highlighter = match highlighter {
+        Ok(highlighter) => highlighter,
+        Err(e) => {
+            eprintln!("Error loading highlighting: {}", e);
+            return;
+        }
+    };
+
+    let options = render::Options {
+        line_numbers: command.line_numbers,
+        title: command.title,
+    };
+    let rendered = match render::render(&bk, &highlighter, command.format, &options) {
+        Ok(rendered) => rendered,
+        Err(e) => {
+            eprintln!("Error rendering bookmark: {}", e);
+            return;
+        }
+    };
+
+    let result = match &command.output {
+        Some(path) => std::fs::write(path, rendered),
+        None => io::stdout().write_all(&rendered),
+    };
+    if let Err(e) = result {
+        eprintln!("Error writing rendered bookmark: {}", e);
+    }
+}
+
+fn list_command(
+    store: &dyn SnippetStore,
+    command: &ListCommand,
+    highlighter: &Highlighter,
+    paging: bool,
+) {
+    let mut bookmarks = match store.all() {
+        Ok(bookmarks) => bookmarks,
+        Err(e) => {
+            eprintln!("Error listing bookmarks: {}", e);
+            return;
+        }
+    };
+
+    view::sort(&mut bookmarks, command.sort);
+    if command.reverse {
+        bookmarks.reverse();
+    }
+
+    let mut footer = None;
+    if let Some(limit) = command.limit {
+        let number = command.page.unwrap_or(1);
+        let pages = view::pages(bookmarks.len(), limit.get()).max(1);
+        if number == 0 || number > pages {
+            eprintln!("Page {} does not exist, there are {} pages", number, pages);
+            return;
+        }
+
+        footer = Some(format!(
+            "Page {} of {}, {} bookmarks",
+            number,
+            pages,
+            bookmarks.len()
+        ));
+        bookmarks = view::page(&bookmarks, limit.get(), number).to_vec();
+    }
+
+    let mut lines = if command.table {
+        view::table(&bookmarks)
+    } else {
+        bookmark_lines(&bookmarks, command.oneline, command.json, highlighter)
+    };
+    if let Some(footer) = footer.filter(|_| !command.json) {
+        if command.table {
+            lines.push(String::new());
+        }
+        lines.push(footer);
+    }
+
+    if let Err(e) = pager::output(&lines, paging) {
+        eprintln!("Error listing bookmarks: {}", e);
+    }
+}
+
+fn search_command(
+    store: &dyn SnippetStore,
+    command: &SearchCommand,
+    highlighter: &Highlighter,
+    paging: bool,
+) {
+    let bookmarks = match store.all() {
+        Ok(bookmarks) => bookmarks,
+        Err(e) => {
+            eprintln!("Error searching bookmarks: {}", e);
+            return;
+        }
+    };
+
+    let found: Vec<_> = bookmarks
+        .into_iter()
+        .filter(|bk| bk.matches(&command.query))
+        .collect();
+    if found.is_empty() && !command.json {
+        eprintln!("No bookmarks found for '{}'", command.query);
+        return;
+    }
+
+    let lines = bookmark_lines(&found, command.oneline, command.json, highlighter);
+    if let Err(e) = pager::output(&lines, paging) {
+        eprintln!("Error searching bookmarks: {}", e);
     }
 }
 
-fn list_command(command: &ListCommand) {
-    let bookmarks = capture::bookmark::get_all_bookmarks().unwrap();
-    for bk in bookmarks {
-        bk.print(!command.oneline);
+fn json_lines(json: &serde_json::Value) -> Vec<String> {
+    format!("{:#}", json).lines().map(String::from).collect()
+}
+
+fn bookmark_lines(
+    bookmarks: &[capture::bookmark::Bookmark],
+    oneline: bool,
+    json: bool,
+    highlighter: &Highlighter,
+) -> Vec<String> {
+    if json {
+        let bookmarks: Vec<_> = bookmarks.iter().map(|bk| bk.to_json()).collect();
+        return json_lines(&serde_json::Value::Array(bookmarks));
+    }
+
+    bookmarks
+        .iter()
+        .flat_map(|bk| bk.display(!oneline, highlighter, &View::default()))
+        .collect()
+}
+
+fn sync_command(store: &mut dyn SnippetStore, command: &SyncCommand) {
+    let conflicts = match store.sync(&command.remote) {
+        Ok(conflicts) => conflicts,
+        Err(e) => {
+            eprintln!("Error syncing bookmarks: {}", e);
+            return;
+        }
+    };
+
+    for conflict in &conflicts {
+        eprintln!(
+            "Conflict: bookmark '{}' is '{}' locally and '{}' in '{}', kept the local version",
+            conflict.name, conflict.local_id, conflict.remote_id, command.remote
+        );
+    }
+    println!("Bookmarks synced with '{}'", command.remote);
+}
+
+fn fsck_command(store: &mut dyn SnippetStore, command: &FsckCommand) {
+    let problems = match store.fsck(command.repair) {
+        Ok(problems) => problems,
+        Err(e) => {
+            eprintln!("Error checking bookmark store: {}", e);
+            return;
+        }
+    };
+
+    for problem in &problems {
+        println!("{}", problem);
+    }
+
+    if problems.is_empty() {
+        println!("No problems found");
+    } else if command.repair {
+        println!("Repaired {} problem(s)", problems.len());
+    } else {
+        println!(
+            "Found {} problem(s), run with --repair to fix them",
+            problems.len()
+        );
     }
 }
 
 fn main() {
     let args = Args::parse();
 
+    if let Action::Init(command) = &args.action {
+        init_command(command);
+        return;
+    }
+    if let Action::Themes = &args.action {
+        themes_command(args.theme.as_deref());
+        return;
+    }
+    if let Some(dir) = Config::dir() {
+        if let Err(e) = definitions::load(&dir) {
+            eprintln!("Error loading languages: {}", e);
+            return;
+        }
+    }
+
+    let mut store = match store::open() {
+        Ok(store) => store,
+        Err(e) => {
+            eprintln!("Error opening bookmark store: {}", e);
+            return;
+        }
+    };
+    let store = store.as_mut();
+
     match args.action {
-        Action::Add(command) => add_command(&command),
-        Action::Delete(command) => delete_command(&command.name),
-        Action::Get(command) => get_command(&command.name),
-        Action::List(command) => list_command(&command),
+        Action::Init(_) => unreachable!(),
+        Action::Add(command) => add_command(store, &command),
+        Action::Delete(command) => delete_command(store, &command.name),
+        Action::Edit(command) => edit_command(store, &command.name),
+        Action::Rename(command) => rename_command(store, &command.name, &command.new_name),
+        Action::Cp(command) => copy_command(store, &command),
+        Action::Mv(command) => move_command(store, &command),
+        Action::Get(command) => {
+            if let Some(highlighter) = load_highlighter(args.color, args.theme.as_deref()) {
+                get_command(store, &command, &highlighter, !args.no_pager)
+            }
+        }
+        Action::Paste(command) => paste_command(store, &command),
+        Action::Export(command) => {
+            if let Some(highlighter) = load_highlighter(args.color, args.theme.as_deref()) {
+                export_command(store, &command, &highlighter)
+            }
+        }
+        Action::Render(command) => render_command(store, &command, args.theme.as_deref()),
+        Action::List(command) => {
+            if let Some(highlighter) = load_highlighter(args.color, args.theme.as_deref()) {
+                list_command(store, &command, &highlighter, !args.no_pager)
+            }
+        }
+        Action::Search(command) => {
+            if let Some(highlighter) = load_highlighter(args.color, args.theme.as_deref()) {
+                search_command(store, &command, &highlighter, !args.no_pager)
+            }
+        }
+        Action::Sync(command) => sync_command(store, &command),
+        Action::Fsck(command) => fsck_command(store, &command),
+        Action::Themes => unreachable!(),
     }
 }
@@ -0,0 +1,143 @@
+use std::env;
+use std::fs::File;
+use std::io::{self, IsTerminal, Write};
+use std::process::{Command, Stdio};
+
+/// Pager used when `$PAGER` is not set. `-R` keeps the colors.
+const DEFAULT_PAGER: &str = "less -R";
+
+/// Command to page `lines` lines with, if any: `pager_var`, the value of
+/// `$PAGER`, or `less -R`. Output is paged only with `paging` on, to a
+/// `terminal` of known `height` it does not fit in. An empty `$PAGER` turns
+/// paging off.
+fn pager_command(
+    lines: usize,
+    paging: bool,
+    pager_var: Option<&str>,
+    terminal: bool,
+    height: Option<usize>,
+) -> Option<String> {
+    if !paging || !terminal || height.is_none_or(|height| lines < height) {
+        return None;
+    }
+
+    match pager_var {
+        Some(pager) if pager.trim().is_empty() => None,
+        Some(pager) => Some(pager.to_string()),
+        None => Some(String::from(DEFAULT_PAGER)),
+    }
+}
+
+/// Rows of the terminal: `$LINES`, or what `stty` reports for it.
+fn terminal_height() -> Option<usize> {
+    if let Some(lines) = env::var("LINES").ok().and_then(|lines| lines.parse().ok()) {
+        return Some(lines).filter(|&lines| lines > 0);
+    }
+
+    let output = Command::new("stty")
+        .arg("size")
+        .stdin(File::open("/dev/tty").ok()?)
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    // Terminals that do not know their size report 0 rows
+    let size = String::from_utf8(output.stdout).ok()?;
+    size.split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+        .filter(|&rows| rows > 0)
+}
+
+/// Prints `lines` to standard output, through the pager if `paging` is on,
+/// standard output is a terminal and they do not fit in it. Output stops
+/// without an error when whatever reads it goes away, like `head`.
+pub fn output(lines: &[String], paging: bool) -> io::Result<()> {
+    let terminal = io::stdout().is_terminal();
+    let height = if paging && terminal {
+        terminal_height()
+    } else {
+        None
+    };
+    let pager_var = env::var("PAGER").ok();
+    if let Some(pager) = pager_command(lines.len(), paging, pager_var.as_deref(), terminal, height)
+    {
+        match page(lines, &pager) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            result => return result,
+        }
+    }
+
+    ignore_broken_pipe(write_lines(&mut io::stdout().lock(), lines))
+}
+
+/// Pipes `lines` to `pager`, split on whitespace like `$EDITOR`, and waits
+/// for the user to quit it.
+fn page(lines: &[String], pager: &str) -> io::Result<()> {
+    let mut args = pager.split_whitespace();
+    let mut command = Command::new(args.next().unwrap_or_default());
+    command.args(args).stdin(Stdio::piped());
+    // Like git, let `less` quit by itself when the output fits after all and
+    // keep it on the screen, unless the user set options of their own
+    if env::var_os("LESS").is_none() {
+        command.env("LESS", "FRX");
+    }
+    let mut child = command.spawn()?;
+
+    // Quitting the pager before reading everything closes the pipe
+    let written = ignore_broken_pipe(write_lines(&mut child.stdin.take().unwrap(), lines));
+    child.wait()?;
+    written
+}
+
+fn write_lines(out: &mut dyn Write, lines: &[String]) -> io::Result<()> {
+    for line in lines {
+        writeln!(out, "{}", line)?;
+    }
+    out.flush()
+}
+
+fn ignore_broken_pipe(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pager_command, write_lines, DEFAULT_PAGER};
+
+    #[test]
+    fn pages_output_taller_than_the_terminal() {
+        let less = Some(String::from(DEFAULT_PAGER));
+        assert_eq!(pager_command(30, true, None, true, Some(24)), less);
+        assert_eq!(pager_command(24, true, None, true, Some(24)), less);
+        assert_eq!(pager_command(10, true, None, true, Some(24)), None);
+        assert_eq!(
+            pager_command(30, true, Some("more"), true, Some(24)),
+            Some(String::from("more"))
+        );
+    }
+
+    #[test]
+    fn turns_paging_off() {
+        // Empty `$PAGER`
+        assert_eq!(pager_command(30, true, Some(""), true, Some(24)), None);
+        assert_eq!(pager_command(30, true, Some("  "), true, Some(24)), None);
+        // `--no-pager`
+        assert_eq!(pager_command(30, false, None, true, Some(24)), None);
+        // Standard output is not a terminal
+        assert_eq!(pager_command(30, true, None, false, None), None);
+        // The size of the terminal is unknown, like when stdin is redirected
+        assert_eq!(pager_command(30, true, None, true, None), None);
+    }
+
+    #[test]
+    fn writes_lines() {
+        let mut out = Vec::new();
+        let lines = vec![String::from("one"), String::from("two")];
+        write_lines(&mut out, &lines).unwrap();
+        assert_eq!(out, b"one\ntwo\n");
+    }
+}
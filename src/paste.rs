@@ -0,0 +1,283 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::bookmark::Bookmark;
+use crate::rules::{indentation, Rule};
+
+/// Where to insert a bookmark in a file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Anchor {
+    /// Before the given line, starting at 1. One past the last line appends
+    Line(usize),
+    /// After the end of the function with the given name
+    AfterFunction(String),
+}
+
+/// A file with a bookmark inserted into it.
+pub struct Paste {
+    /// Content of the file before the bookmark was inserted
+    pub original: String,
+    /// Content of the file with the bookmark inserted
+    pub result: String,
+    /// Line the bookmark starts at in the result, starting at 1
+    pub line: usize,
+}
+
+impl Paste {
+    /// Inserts the content of `bookmark` into the file at `path`, indented
+    /// like the code around it. Nothing is written until `write` is called.
+    pub fn new(bookmark: &Bookmark, path: &Path, anchor: &Anchor) -> io::Result<Self> {
+        let path_str = path.to_str().unwrap_or_default();
+        let original = fs::read_to_string(path)?;
+        let rule = Rule::new(path, &original);
+        if rule.language != bookmark.lang {
+            let err_msg = format!(
+                "Bookmark '{}' is {} code, it cannot be pasted into {}",
+                bookmark.name, bookmark.lang, path_str
+            );
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, err_msg));
+        }
+
+        let mut lines: Vec<String> = original.lines().map(String::from).collect();
+
+        let (idx, indent, separate) = match anchor {
+            Anchor::Line(line) if *line == 0 || *line > lines.len() + 1 => {
+                let err_msg = format!(
+                    "Line {} is out of range, {} has {} lines",
+                    line,
+                    path_str,
+                    lines.len()
+                );
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, err_msg));
+            }
+            Anchor::Line(line) => (line - 1, block_indent(&lines, line - 1), false),
+            Anchor::AfterFunction(name) => match rule.find_function(&lines, name) {
+                Some((start, end)) => {
+                    let indent = lines[start][..indentation(&lines[start])].to_string();
+                    (end + 1, indent, true)
+                }
+                None => {
+                    let err_msg = format!("Function '{}' not found in {}", name, path_str);
+                    return Err(io::Error::new(io::ErrorKind::NotFound, err_msg));
+                }
+            },
+        };
+
+        // Functions pasted after another are kept apart by a blank line
+        let mut snippet = reindent(&bookmark.content, &indent);
+        if separate {
+            snippet.insert(0, String::new());
+        }
+        let line = idx + 1 + usize::from(separate);
+        lines.splice(idx..idx, snippet);
+
+        let mut result = lines.join("\n");
+        if original.ends_with('\n') || original.is_empty() {
+            result.push('\n');
+        }
+
+        Ok(Paste {
+            original,
+            result,
+            line,
+        })
+    }
+
+    /// Unified diff of the file before and after the bookmark is inserted.
+    pub fn diff(&self, path: &Path) -> String {
+        let path = path.to_string_lossy();
+        similar::TextDiff::from_lines(&self.original, &self.result)
+            .unified_diff()
+            .header(&path, &path)
+            .to_string()
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, &self.result)
+    }
+}
+
+/// Indentation of the block a line inserted before `lines[idx]` ends up in:
+/// the one of the line it pushes down, unless that line closes the block, in
+/// which case it is one level deeper than the line before it.
+fn block_indent(lines: &[String], idx: usize) -> String {
+    let next = lines[idx..].iter().find(|line| !line.trim().is_empty());
+    let previous = lines[..idx]
+        .iter()
+        .rev()
+        .find(|line| !line.trim().is_empty());
+
+    let closes_block = |line: &String| line.trim_start().starts_with(['}', ')', ']']);
+    match (previous, next) {
+        (_, Some(next)) if !closes_block(next) => next[..indentation(next)].to_string(),
+        (Some(previous), _) => {
+            let mut indent = previous[..indentation(previous)].to_string();
+            if previous.trim_end().ends_with(['{', '(', '[', ':']) {
+                indent.push_str(&indent_unit(lines));
+            }
+            indent
+        }
+        (None, _) => String::new(),
+    }
+}
+
+/// Indentation used for one level in `lines`: a tab, or the smallest number
+/// of spaces any line is indented with.
+fn indent_unit(lines: &[String]) -> String {
+    if lines.iter().any(|line| line.starts_with('\t')) {
+        return String::from("\t");
+    }
+
+    let spaces = lines
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| indentation(line))
+        .filter(|spaces| *spaces > 0)
+        .min()
+        .unwrap_or(4);
+    " ".repeat(spaces)
+}
+
+/// Strips the indentation common to every line of `content` and indents it
+/// with `indent` instead. Only the same leading whitespace counts as common,
+/// so a tab is not stripped from one line and a space from another. Blank
+/// lines are left empty.
+fn reindent(content: &[String], indent: &str) -> Vec<String> {
+    let common = content
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| &line[..indentation(line)])
+        .reduce(|common, other| {
+            let len = common
+                .char_indices()
+                .zip(other.chars())
+                .take_while(|((_, a), b)| a == b)
+                .last()
+                .map_or(0, |((idx, c), _)| idx + c.len_utf8());
+            &common[..len]
+        })
+        .unwrap_or_default();
+
+    content
+        .iter()
+        .map(|line| {
+            if line.trim().is_empty() {
+                String::new()
+            } else {
+                format!("{}{}", indent, line.strip_prefix(common).unwrap_or(line))
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{reindent, Anchor, Paste};
+    use crate::bookmark::Bookmark;
+    use crate::language::Language;
+    use std::fs;
+    use std::io;
+
+    fn bookmark(content: &[&str], lang: Language) -> Bookmark {
+        let content: Vec<String> = content.iter().map(|line| line.to_string()).collect();
+        Bookmark::new("snippet", &content, lang)
+    }
+
+    const FILE: &str = "fn main() {\n    let a = 1;\n\n    println!(\"{}\", a);\n}\n";
+
+    #[test]
+    fn pastes_at_line_with_block_indentation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("main.rs");
+        fs::write(&path, FILE).unwrap();
+
+        let snippet = bookmark(&["if a > 0 {", "    a += 1;", "}"], Language::Rust);
+        let paste = Paste::new(&snippet, &path, &Anchor::Line(3)).unwrap();
+        assert_eq!(paste.line, 3);
+        assert_eq!(
+            paste.result,
+            "fn main() {\n    let a = 1;\n    if a > 0 {\n        a += 1;\n    }\n\n    println!(\"{}\", a);\n}\n"
+        );
+
+        // Before the closing brace the snippet goes inside the block
+        let paste = Paste::new(&snippet, &path, &Anchor::Line(5)).unwrap();
+        assert!(paste
+            .result
+            .ends_with("a);\n    if a > 0 {\n        a += 1;\n    }\n}\n"));
+
+        // Appending at the end of the file
+        let paste = Paste::new(&snippet, &path, &Anchor::Line(6)).unwrap();
+        assert!(paste.result.ends_with("}\nif a > 0 {\n    a += 1;\n}\n"));
+
+        assert!(Paste::new(&snippet, &path, &Anchor::Line(7)).is_err());
+        assert!(Paste::new(&snippet, &path, &Anchor::Line(0)).is_err());
+    }
+
+    #[test]
+    fn pastes_after_function() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lib.rs");
+        fs::write(&path, "impl A {\n    fn one() {\n    }\n}\n").unwrap();
+
+        let snippet = bookmark(&["fn two() {", "}"], Language::Rust);
+        let paste = Paste::new(&snippet, &path, &Anchor::AfterFunction("one".into())).unwrap();
+        assert_eq!(paste.line, 5);
+        assert_eq!(
+            paste.result,
+            "impl A {\n    fn one() {\n    }\n\n    fn two() {\n    }\n}\n"
+        );
+
+        let err = Paste::new(&snippet, &path, &Anchor::AfterFunction("three".into()))
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn writes_only_when_asked() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("main.rs");
+        fs::write(&path, FILE).unwrap();
+
+        let snippet = bookmark(&["let b = 2;"], Language::Rust);
+        let paste = Paste::new(&snippet, &path, &Anchor::Line(3)).unwrap();
+        assert!(paste.diff(&path).contains("\n+    let b = 2;\n"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), FILE);
+
+        paste.write(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), paste.result);
+    }
+
+    #[test]
+    fn refuses_other_languages() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("main.py");
+        fs::write(&path, "print(1)\n").unwrap();
+
+        let snippet = bookmark(&["let b = 2;"], Language::Rust);
+        let err = Paste::new(&snippet, &path, &Anchor::Line(1)).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn strips_only_common_indentation() {
+        let lines =
+            |lines: &[&str]| -> Vec<String> { lines.iter().map(|line| line.to_string()).collect() };
+
+        // A tab and a space are not the same indentation
+        assert_eq!(
+            reindent(&lines(&["\tif a {", " b", "\t}"]), "  "),
+            vec!["  \tif a {", "   b", "  \t}"]
+        );
+        // Nor is a no-break space, which takes two bytes
+        assert_eq!(
+            reindent(&lines(&["\u{a0}x", " x"]), ""),
+            vec!["\u{a0}x", " x"]
+        );
+        assert_eq!(
+            reindent(&lines(&["\u{a0} x", "", "\u{a0}\u{a0}y"]), "\t"),
+            vec!["\t x", "", "\t\u{a0}y"]
+        );
+    }
+}
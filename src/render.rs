@@ -0,0 +1,528 @@
+use base64::Engine;
+use fontdue::{Font, FontSettings};
+use lazy_static::lazy_static;
+use std::io;
+use std::str::FromStr;
+use syntect::highlighting::{Color, Style};
+use syntect::html::{styled_line_to_highlighted_html, IncludeBackground};
+
+use crate::bookmark::Bookmark;
+use crate::highlight::Highlighter;
+use crate::language::Language;
+
+/// Font code images are drawn with, so they look the same everywhere.
+const FONT_DATA: &[u8] = include_bytes!("../assets/DejaVuSansMono.ttf");
+const FONT_FAMILY: &str = "DejaVu Sans Mono";
+
+lazy_static! {
+    static ref FONT: Font = Font::from_bytes(FONT_DATA, FontSettings::default()).unwrap();
+}
+
+/// Size of the font in SVG images, PNG images are drawn at twice the size.
+const FONT_SIZE: f32 = 14.0;
+const TAB_WIDTH: usize = 4;
+
+/// Formats a bookmark can be rendered to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Html,
+    Md,
+    Svg,
+    Png,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "html" => Ok(Format::Html),
+            "md" => Ok(Format::Md),
+            "svg" => Ok(Format::Svg),
+            "png" => Ok(Format::Png),
+            _ => Err(format!(
+                "Unknown format '{}', expected html, md, svg or png",
+                format
+            )),
+        }
+    }
+}
+
+/// What is rendered along with the code.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Options {
+    /// Number of each line in a gutter
+    pub line_numbers: bool,
+    /// Bar above the code with the name of the bookmark
+    pub title: bool,
+}
+
+/// Renders `bookmark` highlighted with the theme of `highlighter`.
+pub fn render(
+    bookmark: &Bookmark,
+    highlighter: &Highlighter,
+    format: Format,
+    options: &Options,
+) -> io::Result<Vec<u8>> {
+    match format {
+        Format::Html => html(bookmark, highlighter, options).map(String::into_bytes),
+        Format::Md => Ok(markdown(bookmark, options).into_bytes()),
+        Format::Svg => Ok(svg(&Image::new(bookmark, highlighter, options, 1.0)).into_bytes()),
+        Format::Png => png(&Image::new(bookmark, highlighter, options, 2.0)),
+    }
+}
+
+fn hex(color: Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
+
+/// Color halfway between `a` and `b`, used for line numbers and titles.
+fn blend(a: Color, b: Color) -> Color {
+    let mix = |x: u8, y: u8| ((x as u16 + y as u16) / 2) as u8;
+    Color {
+        r: mix(a.r, b.r),
+        g: mix(a.g, b.g),
+        b: mix(a.b, b.b),
+        a: 255,
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Width of the gutter, in characters, for `lines` lines.
+fn gutter_width(lines: usize) -> usize {
+    lines.max(1).to_string().len()
+}
+
+/// A `<pre>` block with inline styles, so it can be pasted into any page.
+fn html(bookmark: &Bookmark, highlighter: &Highlighter, options: &Options) -> io::Result<String> {
+    let (background, foreground) = highlighter.theme_colors();
+    let dim = hex(blend(background, foreground));
+    let width = gutter_width(bookmark.content.len());
+
+    let mut html = format!(
+        "<div class=\"capture-snippet\" style=\"background-color:{};color:{};border-radius:6px;overflow:auto;\">\n",
+        hex(background),
+        hex(foreground)
+    );
+    if options.title {
+        html.push_str(&format!(
+            "<div style=\"padding:6px 12px;border-bottom:1px solid {};font-family:sans-serif;\">{}</div>\n",
+            dim,
+            escape_xml(&bookmark.name)
+        ));
+    }
+
+    html.push_str("<pre style=\"margin:0;padding:12px;\"><code>");
+    for (idx, ranges) in highlighter
+        .styles(&bookmark.lang, &bookmark.content)
+        .iter()
+        .enumerate()
+    {
+        if options.line_numbers {
+            html.push_str(&format!(
+                "<span style=\"color:{};user-select:none;\">{:>width$} </span>",
+                dim,
+                idx + 1
+            ));
+        }
+
+        let ranges: Vec<(Style, &str)> = ranges
+            .iter()
+            .map(|(style, text)| (*style, text.as_str()))
+            .collect();
+        let line = styled_line_to_highlighted_html(&ranges, IncludeBackground::No)
+            .map_err(io::Error::other)?;
+        html.push_str(&line);
+        html.push('\n');
+    }
+    html.push_str("</code></pre>\n</div>\n");
+
+    Ok(html)
+}
+
+/// A fenced code block, highlighted by whatever displays the markdown.
+fn markdown(bookmark: &Bookmark, options: &Options) -> String {
+    let mut markdown = String::new();
+    if options.title {
+        markdown.push_str(&format!("**{}**\n\n", bookmark.name));
+    }
+
+    // Fences are made longer than any run of backticks in the code
+    let longest = bookmark
+        .content
+        .iter()
+        .flat_map(|line| line.split(|c| c != '`'))
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    let fence = "`".repeat(longest.max(2) + 1);
+    let info = match bookmark.lang {
+        Language::Unknown => String::new(),
+        _ => bookmark.lang.name().to_lowercase(),
+    };
+
+    markdown.push_str(&format!("{}{}\n", fence, info));
+    let width = gutter_width(bookmark.content.len());
+    for (idx, line) in bookmark.content.iter().enumerate() {
+        if options.line_numbers {
+            markdown.push_str(&format!("{:>width$} | ", idx + 1));
+        }
+        markdown.push_str(line);
+        markdown.push('\n');
+    }
+    markdown.push_str(&fence);
+    markdown.push('\n');
+
+    markdown
+}
+
+/// Code laid out on a grid of monospace cells, shared by the SVG and PNG
+/// renderers.
+struct Image {
+    title: Option<String>,
+    /// Pieces of text of each line, with their color
+    lines: Vec<Vec<(Color, String)>>,
+    background: Color,
+    dim: Color,
+    font_size: f32,
+    char_width: f32,
+    line_height: f32,
+    ascent: f32,
+    padding: f32,
+    title_height: f32,
+    width: u32,
+    height: u32,
+}
+
+impl Image {
+    fn new(bookmark: &Bookmark, highlighter: &Highlighter, options: &Options, scale: f32) -> Self {
+        let (background, foreground) = highlighter.theme_colors();
+        let dim = blend(background, foreground);
+        let width = gutter_width(bookmark.content.len());
+
+        let lines: Vec<Vec<(Color, String)>> = highlighter
+            .styles(&bookmark.lang, &bookmark.content)
+            .into_iter()
+            .enumerate()
+            .map(|(idx, ranges)| {
+                let mut pieces = Vec::new();
+                if options.line_numbers {
+                    pieces.push((dim, format!("{:>width$}  ", idx + 1)));
+                }
+                pieces.extend(ranges.into_iter().map(|(style, text)| {
+                    (style.foreground, text.replace('\t', &" ".repeat(TAB_WIDTH)))
+                }));
+                pieces
+            })
+            .collect();
+
+        let font_size = FONT_SIZE * scale;
+        let char_width = FONT.metrics('M', font_size).advance_width;
+        let ascent = FONT
+            .horizontal_line_metrics(font_size)
+            .map_or(font_size, |metrics| metrics.ascent);
+        let line_height = (font_size * 1.5).round();
+        let padding = 16.0 * scale;
+        let title_height = if options.title { 32.0 * scale } else { 0.0 };
+
+        let columns = lines
+            .iter()
+            .map(|pieces| {
+                pieces
+                    .iter()
+                    .map(|(_, text)| text.chars().count())
+                    .sum::<usize>()
+            })
+            .max()
+            .unwrap_or(0);
+
+        Image {
+            title: options.title.then(|| bookmark.name.clone()),
+            width: (padding * 2.0 + columns as f32 * char_width).ceil() as u32,
+            height: (title_height + padding * 2.0 + lines.len() as f32 * line_height).ceil() as u32,
+            lines,
+            background,
+            dim,
+            font_size,
+            char_width,
+            line_height,
+            ascent,
+            padding,
+            title_height,
+        }
+    }
+
+    /// Position of the baseline of line `idx`.
+    fn baseline(&self, idx: usize) -> f32 {
+        self.title_height + self.padding + idx as f32 * self.line_height + self.ascent
+    }
+
+    /// Centers of the window buttons drawn in the title bar, with their color.
+    fn buttons(&self) -> [(f32, f32, Color); 3] {
+        let y = self.title_height / 2.0;
+        let step = self.font_size * 1.4;
+        let color = |r, g, b| Color { r, g, b, a: 255 };
+        [
+            (self.padding, y, color(0xff, 0x5f, 0x56)),
+            (self.padding + step, y, color(0xff, 0xbd, 0x2e)),
+            (self.padding + step * 2.0, y, color(0x27, 0xc9, 0x3f)),
+        ]
+    }
+
+    /// Where the title starts so it is centered, and its baseline.
+    fn title_position(&self, title: &str) -> (f32, f32) {
+        let width = title.chars().count() as f32 * self.char_width;
+        let x = ((self.width as f32 - width) / 2.0).max(self.padding * 4.0);
+        let y = (self.title_height + self.ascent) / 2.0 - self.font_size * 0.1;
+        (x, y)
+    }
+}
+
+/// Standalone SVG image, with the font embedded.
+fn svg(image: &Image) -> String {
+    let font = base64::engine::general_purpose::STANDARD.encode(FONT_DATA);
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n",
+        w = image.width,
+        h = image.height
+    );
+    svg.push_str(&format!(
+        "<style>@font-face {{ font-family: \"{family}\"; src: url(data:font/ttf;base64,{font}); }} \
+         text {{ font-family: \"{family}\", monospace; font-size: {size}px; white-space: pre; }}</style>\n",
+        family = FONT_FAMILY,
+        font = font,
+        size = image.font_size
+    ));
+    svg.push_str(&format!(
+        "<rect width=\"{}\" height=\"{}\" rx=\"8\" fill=\"{}\"/>\n",
+        image.width,
+        image.height,
+        hex(image.background)
+    ));
+
+    if let Some(title) = &image.title {
+        for (x, y, color) in image.buttons() {
+            svg.push_str(&format!(
+                "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{}\"/>\n",
+                x + image.font_size / 2.0,
+                y,
+                image.font_size / 2.0,
+                hex(color)
+            ));
+        }
+        let (x, y) = image.title_position(title);
+        svg.push_str(&format!(
+            "<text x=\"{}\" y=\"{}\" fill=\"{}\">{}</text>\n",
+            x,
+            y,
+            hex(image.dim),
+            escape_xml(title)
+        ));
+    }
+
+    for (idx, pieces) in image.lines.iter().enumerate() {
+        svg.push_str(&format!(
+            "<text x=\"{}\" y=\"{}\" xml:space=\"preserve\">",
+            image.padding,
+            image.baseline(idx)
+        ));
+        for (color, text) in pieces {
+            svg.push_str(&format!(
+                "<tspan fill=\"{}\">{}</tspan>",
+                hex(*color),
+                escape_xml(text)
+            ));
+        }
+        svg.push_str("</text>\n");
+    }
+    svg.push_str("</svg>\n");
+
+    svg
+}
+
+/// RGBA pixels the PNG image is drawn on.
+struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(width: u32, height: u32, background: Color) -> Self {
+        let pixel = [background.r, background.g, background.b, 255];
+        Canvas {
+            width,
+            height,
+            pixels: pixel.repeat((width * height) as usize),
+        }
+    }
+
+    /// Mixes `color` into the pixel at `x`, `y` by `coverage`, from 0 to 1.
+    fn blend(&mut self, x: i32, y: i32, color: Color, coverage: f32) {
+        if x < 0 || y < 0 || x as u32 >= self.width || y as u32 >= self.height {
+            return;
+        }
+
+        let idx = ((y as u32 * self.width + x as u32) * 4) as usize;
+        let mix = |from: u8, to: u8| (from as f32 + (to as f32 - from as f32) * coverage) as u8;
+        self.pixels[idx] = mix(self.pixels[idx], color.r);
+        self.pixels[idx + 1] = mix(self.pixels[idx + 1], color.g);
+        self.pixels[idx + 2] = mix(self.pixels[idx + 2], color.b);
+    }
+
+    /// Draws `text` starting at `x` on the baseline `y`, one cell per
+    /// character.
+    fn text(&mut self, x: f32, y: f32, text: &str, color: Color, size: f32, char_width: f32) {
+        for (idx, c) in text.chars().enumerate() {
+            if c.is_whitespace() {
+                continue;
+            }
+
+            let (metrics, bitmap) = FONT.rasterize(c, size);
+            let left = (x + idx as f32 * char_width).round() as i32 + metrics.xmin;
+            let top = y.round() as i32 - metrics.height as i32 - metrics.ymin;
+            for (offset, coverage) in bitmap.iter().enumerate() {
+                if *coverage > 0 {
+                    let dx = (offset % metrics.width) as i32;
+                    let dy = (offset / metrics.width) as i32;
+                    self.blend(left + dx, top + dy, color, *coverage as f32 / 255.0);
+                }
+            }
+        }
+    }
+
+    fn circle(&mut self, cx: f32, cy: f32, radius: f32, color: Color) {
+        let (min_x, max_x) = ((cx - radius) as i32 - 1, (cx + radius) as i32 + 1);
+        let (min_y, max_y) = ((cy - radius) as i32 - 1, (cy + radius) as i32 + 1);
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                let distance =
+                    ((x as f32 + 0.5 - cx).powi(2) + (y as f32 + 0.5 - cy).powi(2)).sqrt();
+                let coverage = (radius - distance + 0.5).clamp(0.0, 1.0);
+                if coverage > 0.0 {
+                    self.blend(x, y, color, coverage);
+                }
+            }
+        }
+    }
+}
+
+fn png(image: &Image) -> io::Result<Vec<u8>> {
+    let mut canvas = Canvas::new(image.width, image.height, image.background);
+
+    if let Some(title) = &image.title {
+        for (x, y, color) in image.buttons() {
+            let radius = image.font_size / 2.0;
+            canvas.circle(x + radius, y, radius, color);
+        }
+        let (x, y) = image.title_position(title);
+        canvas.text(x, y, title, image.dim, image.font_size, image.char_width);
+    }
+
+    for (idx, pieces) in image.lines.iter().enumerate() {
+        let mut x = image.padding;
+        for (color, text) in pieces {
+            canvas.text(
+                x,
+                image.baseline(idx),
+                text,
+                *color,
+                image.font_size,
+                image.char_width,
+            );
+            x += text.chars().count() as f32 * image.char_width;
+        }
+    }
+
+    let mut data = Vec::new();
+    let mut encoder = png::Encoder::new(&mut data, canvas.width, canvas.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    writer
+        .write_image_data(&canvas.pixels)
+        .map_err(io::Error::other)?;
+    writer.finish().map_err(io::Error::other)?;
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render, Format, Options};
+    use crate::bookmark::Bookmark;
+    use crate::color::Colors;
+    use crate::highlight::{Highlighter, DEFAULT_THEME};
+    use crate::language::Language;
+
+    fn bookmark() -> Bookmark {
+        let content: Vec<String> = ["fn one() -> u8 {", "    1 << 0", "}"]
+            .iter()
+            .map(|line| line.to_string())
+            .collect();
+        Bookmark::new("rust/one", &content, Language::Rust)
+    }
+
+    fn rendered(format: Format, options: &Options) -> Vec<u8> {
+        let highlighter = Highlighter::new(None, DEFAULT_THEME, Colors::TrueColor).unwrap();
+        render(&bookmark(), &highlighter, format, options).unwrap()
+    }
+
+    #[test]
+    fn renders_html() {
+        let options = Options {
+            line_numbers: true,
+            title: true,
+        };
+        let html = String::from_utf8(rendered(Format::Html, &options)).unwrap();
+        assert!(
+            html.starts_with("<div class=\"capture-snippet\" style=\"background-color:#2b303b;")
+        );
+        assert!(html.contains(">rust/one</div>"));
+        assert!(html.contains("user-select:none;\">2 </span>"));
+        assert!(html.contains("&lt;&lt;"));
+    }
+
+    #[test]
+    fn renders_markdown() {
+        let markdown = String::from_utf8(rendered(Format::Md, &Options::default())).unwrap();
+        assert_eq!(markdown, "```rust\nfn one() -> u8 {\n    1 << 0\n}\n```\n");
+
+        let mut backticks = bookmark();
+        backticks.content = vec![String::from("// ```")];
+        backticks.lang = Language::Unknown;
+        let options = Options {
+            line_numbers: true,
+            title: true,
+        };
+        let markdown = super::markdown(&backticks, &options);
+        assert_eq!(markdown, "**rust/one**\n\n````\n1 | // ```\n````\n");
+    }
+
+    #[test]
+    fn renders_images() {
+        let svg = String::from_utf8(rendered(Format::Svg, &Options::default())).unwrap();
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(svg.contains("src: url(data:font/ttf;base64,"));
+        assert_eq!(svg.matches("xml:space=\"preserve\"").count(), 3);
+        assert!(svg.contains("&lt;") && !svg.contains("<<"));
+
+        let png = rendered(Format::Png, &Options::default());
+        let decoder = png::Decoder::new(png.as_slice());
+        let mut reader = decoder.read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        assert!(info.width > 200 && info.height > 100);
+
+        // Something other than the background was drawn
+        let background = &pixels[..4];
+        assert!(pixels.chunks(4).any(|pixel| pixel != background));
+    }
+}
@@ -1,5 +1,18 @@
+use crate::definitions::{Blocks, Definition, Keys};
+use crate::keys;
 use crate::language::Language;
+use crate::statements;
+use lazy_static::lazy_static;
+use regex::Regex;
 use std::path;
+use std::sync::Arc;
+use syntect::parsing::SyntaxSet;
+
+lazy_static! {
+    static ref WORD: Regex = Regex::new(r"[A-Za-z_][A-Za-z0-9_]*[?!]?").unwrap();
+    static ref STRING: Regex = Regex::new(r#""(?:\\.|[^"\\])*"|'(?:\\.|[^'\\])*'"#).unwrap();
+    static ref SYNTAXES: SyntaxSet = SyntaxSet::load_defaults_newlines();
+}
 
 #[derive(Debug, PartialEq)]
 pub enum CommentType {
@@ -12,44 +25,291 @@ pub enum CommentType {
 pub struct Rule {
     pub language: Language,
     pub delimiter: (String, String),
+    /// Definition of the language, looked up once rather than for every line
+    definition: Arc<Definition>,
 }
 
 impl Rule {
-    pub fn new(path: &path::Path) -> Option<Self> {
-        let extension = path.extension()?.to_str().unwrap();
-        let language = Language::from_extension(&extension);
+    /// Rule for the file at `path` with `content`, in the language detected
+    /// from them.
+    pub fn new(path: &path::Path, content: &str) -> Self {
+        Rule::with_language(detect_language(path, content))
+    }
+
+    pub fn with_language(language: Language) -> Self {
+        Rule {
+            language: language.clone(),
+            ..Rule::with_definition(language.definition().unwrap_or_default())
+        }
+    }
 
-        Some(Rule {
-            language,
+    /// Rule for the language `definition` defines, whether or not it is
+    /// among the loaded definitions.
+    pub(crate) fn with_definition(definition: Arc<Definition>) -> Self {
+        Rule {
+            language: Language::from_definition(&definition),
             delimiter: (String::from("{"), String::from("}")),
-        })
+            definition,
+        }
     }
 
-    pub fn contains_function(&self, line: &String, function_name: &String) -> bool {
-        let function_syntax = self.language.get_function_syntax();
-        if !function_syntax.is_match(&line) {
+    pub fn contains_function(&self, line: &str, function_name: &str) -> bool {
+        let Some(function_syntax) = self.definition.function_syntax() else {
             return false;
+        };
+
+        declares(function_syntax, line, function_name)
+    }
+
+    /// Lines where the function `name` starts and ends in `lines`, as
+    /// indexes. Python functions and expression bodies end with their
+    /// indented lines, functions of languages like Ruby with the keyword that
+    /// balances their blocks, the rest with the delimiter that closes the
+    /// first one opened, or the parenthesis if that comes first, as in arrow
+    /// functions returning JSX. Annotations right above the function are part of it, in
+    /// languages with classes `Outer.Inner.function` finds the function
+    /// nested in them, and in single-file components sections like
+    /// `template` are found by their tag.
+    pub fn find_function(&self, lines: &[String], name: &str) -> Option<(usize, usize)> {
+        if self
+            .definition
+            .sections
+            .iter()
+            .any(|section| section == name)
+        {
+            return section_bounds(lines, name);
+        }
+        if self.has_statements() {
+            return statements::find_create(lines, &["FUNCTION", "PROCEDURE"], name);
+        }
+
+        let (mut first, mut last) = (0, lines.len());
+        let mut name = name;
+        if let Some(class_syntax) = self.definition.class_syntax() {
+            if let Some((classes, function)) = name.rsplit_once('.') {
+                for class in classes.split('.') {
+                    let start =
+                        (first..last).find(|idx| declares(class_syntax, &lines[*idx], class))?;
+                    last = self.block_end(lines, start)?;
+                    first = start + 1;
+                }
+                name = function;
+            }
+        }
+
+        let start = (first..last).find(|idx| self.contains_function(&lines[*idx], name))?;
+        let blocks = self.definition.blocks;
+        let end = if blocks == Blocks::Indent || self.has_expression_body(&lines[start]) {
+            indented_block_end(lines, start)
+        } else if blocks == Blocks::Clauses {
+            self.clauses_end(lines, start, name)
+        } else if self.has_no_body(&lines[start]) {
+            start
+        } else if blocks == Blocks::Keywords {
+            self.keyword_block_end(lines, start)?
+        } else if lines[start].trim_end().ends_with('(') {
+            delimited_end(lines, start, "(", ")")?
+        } else {
+            self.block_end(lines, start)?
+        };
+
+        let start = (first..start)
+            .rev()
+            .take_while(|idx| self.is_annotation(&lines[*idx]))
+            .last()
+            .unwrap_or(start);
+        Some((start, end))
+    }
+
+    /// Lines where the value at the dotted `path` starts and ends, in the
+    /// languages that have keys.
+    pub fn find_key(&self, lines: &[String], path: &str) -> Option<(usize, usize)> {
+        match self.definition.keys? {
+            Keys::Yaml => keys::yaml(lines, path),
+            Keys::Toml => keys::toml(lines, path),
+            Keys::Json => keys::json(lines, path),
+        }
+    }
+
+    /// Whether values can be captured by key, as in YAML.
+    pub fn has_keys(&self) -> bool {
+        self.definition.keys.is_some()
+    }
+
+    /// Whether code is made of SQL statements, found by number.
+    pub fn has_statements(&self) -> bool {
+        self.definition.blocks == Blocks::Statements
+    }
+
+    /// Whether code is in fenced blocks, as in Markdown.
+    pub fn has_fences(&self) -> bool {
+        self.definition.fences
+    }
+
+    /// Lines of the SQL statement `number`, starting at 1.
+    pub fn find_statement(&self, lines: &[String], number: usize) -> Option<(usize, usize)> {
+        let statements = statements::statements(lines);
+        number
+            .checked_sub(1)
+            .and_then(|idx| statements.get(idx).copied())
+    }
+
+    /// Lines of the statement creating the SQL view `name`.
+    pub fn find_view(&self, lines: &[String], name: &str) -> Option<(usize, usize)> {
+        statements::find_create(lines, &["VIEW"], name)
+    }
+
+    /// Line closing the first delimiter opened from `start` on.
+    fn block_end(&self, lines: &[String], start: usize) -> Option<usize> {
+        let (open, close) = &self.delimiter;
+        delimited_end(lines, start, open, close)
+    }
+
+    /// Last line of the binding at `start` with its indented lines, and of
+    /// the equations of `name` right after it, like the ones Haskell defines
+    /// a function with, one per pattern.
+    fn clauses_end(&self, lines: &[String], start: usize, name: &str) -> usize {
+        let indent = indentation(&lines[start]);
+        let mut end = indented_block_end(lines, start);
+        while let Some(next) = (end + 1..lines.len()).find(|idx| {
+            let line = lines[*idx].trim();
+            !line.is_empty() && self.contains_comment(line) != Some(CommentType::SingleLine)
+        }) {
+            let first_word = lines[next]
+                .trim_start()
+                .split(|c: char| !c.is_alphanumeric() && c != '_' && c != '\'')
+                .next();
+            if indentation(&lines[next]) != indent || first_word != Some(name) {
+                break;
+            }
+            end = indented_block_end(lines, next);
+        }
+
+        end
+    }
+
+    /// Line where the keyword blocks opened from `start` on are all closed.
+    fn keyword_block_end(&self, lines: &[String], start: usize) -> Option<usize> {
+        let mut depth = 0;
+        for (idx, line) in lines.iter().enumerate().skip(start) {
+            depth += self.keyword_depth(line);
+            if depth <= 0 {
+                return Some(idx);
+            }
+        }
+
+        None
+    }
+
+    /// Number of keyword blocks `line` opens, minus the ones it closes.
+    /// Strings, comments, method calls like `range.end`, symbols and keyword
+    /// arguments like `do:` are left out.
+    fn keyword_depth(&self, line: &str) -> isize {
+        let Some(keywords) = &self.definition.keywords else {
+            return 0;
+        };
+
+        let single_line = &self.definition.comments.line;
+        let code = STRING.replace_all(line, "\"\"");
+        let code = match code.find(single_line.as_str()) {
+            Some(comment) if !single_line.is_empty() => &code[..comment],
+            _ => &code[..],
+        };
+
+        let mut depth = 0;
+        let mut first = None;
+        for word in WORD.find_iter(code) {
+            let before = &code[..word.start()];
+            let after = &code[word.end()..];
+            if before.ends_with(['.', ':', '@', '$']) || after.starts_with(':') {
+                continue;
+            }
+
+            let word = word.as_str();
+            let first = *first.get_or_insert(word);
+            if keywords.close.iter().any(|closer| closer == word) {
+                depth -= 1;
+            } else if keywords.open.iter().any(|opener| opener == word)
+                && self.opens_block(word, before, first)
+            {
+                depth += 1;
+            }
         }
 
-        match function_syntax.captures(&line) {
-            Some(cap) => cap.get(1).unwrap().as_str() == function_name.as_str(),
+        depth
+    }
+
+    /// Whether the keyword `word`, after `before` in a line starting with the
+    /// word `first`, opens a block. Modifiers like Ruby's conditions and
+    /// loops may also modify a single statement, like `return if done`, and
+    /// loops may be followed by a `do` that is part of them.
+    fn opens_block(&self, word: &str, before: &str, first: &str) -> bool {
+        let Some(keywords) = &self.definition.keywords else {
+            return true;
+        };
+
+        if keywords.modifiers.iter().any(|modifier| modifier == word) {
+            let before = before.trim_end();
+            before.is_empty() || before.ends_with(['=', '(', '[', '{', ',', '|', '&'])
+        } else if word == "do" {
+            !keywords.loops.iter().any(|keyword| keyword == first)
+        } else {
+            true
+        }
+    }
+
+    /// Whether the function declared in `line` is a single expression, like
+    /// `fun one() = 1` in Kotlin or `int One() => 1;` in C#.
+    fn has_expression_body(&self, line: &str) -> bool {
+        match &self.definition.expression {
+            Some(expression) => {
+                !line.contains(self.delimiter.0.as_str()) && line.contains(expression.as_str())
+            }
             None => false,
         }
     }
 
-    pub fn contains_comment(&self, line: &String) -> Option<CommentType> {
-        let (single_line, multi_line_start, multi_line_end) = self.language.get_comment_delimiters();
+    /// Whether the function declared in `line` has no body, like abstract
+    /// functions in Kotlin.
+    fn has_no_body(&self, line: &str) -> bool {
+        self.definition.optional_body && !line.contains(self.delimiter.0.as_str())
+    }
+
+    /// Whether `line` is an annotation or attribute of the declaration below
+    /// it, or the template parameters of a C++ function.
+    fn is_annotation(&self, line: &str) -> bool {
+        self.definition
+            .annotation_syntax()
+            .is_some_and(|syntax| syntax.is_match(line))
+    }
 
+    pub fn contains_comment(&self, line: &str) -> Option<CommentType> {
+        let comments = &self.definition.comments;
+        let single_line = comments.line.as_str();
+        let (multi_line_start, multi_line_end) = match &comments.block {
+            Some((start, end)) => (start.as_str(), end.as_str()),
+            None => ("", ""),
+        };
+
+        // Block comments are checked first, as they may start like a single
+        // line one, such as `--[[` and `--` in Lua
         let trimmed = line.trim();
-        if trimmed.starts_with(&single_line) {
-            return Some(CommentType::SingleLine);
-        } else if trimmed.starts_with(&multi_line_start)
-            && trimmed.ends_with(&multi_line_end)
+        // Pragmas look like block comments, but are not
+        if let Some(pragma) = &comments.pragma {
+            if trimmed.starts_with(pragma.as_str()) {
+                return None;
+            }
+        }
+
+        let has_blocks = !multi_line_start.is_empty();
+        if has_blocks && trimmed.starts_with(multi_line_start) && trimmed.ends_with(multi_line_end)
         {
             return Some(CommentType::MultiLineComplete);
-        } else if trimmed.starts_with(&multi_line_start) {
+        } else if has_blocks && trimmed.starts_with(multi_line_start) {
             return Some(CommentType::MultiLineStart);
-        } else if trimmed.ends_with(&multi_line_end) {
+        } else if !single_line.is_empty() && trimmed.starts_with(single_line) {
+            return Some(CommentType::SingleLine);
+        } else if has_blocks && trimmed.ends_with(multi_line_end) {
             return Some(CommentType::MultiLineEnd);
         }
 
@@ -57,10 +317,128 @@ impl Rule {
     }
 }
 
+/// Number of lines at each end of a file where modelines are looked for, as
+/// in Vim.
+const MODELINES: usize = 5;
+
+/// Language of the file at `path` with `content`. It comes from its
+/// extension, otherwise from its name like `Makefile`, its shebang, a
+/// modeline, or the syntax whose first line matches, in that order.
+fn detect_language(path: &path::Path, content: &str) -> Language {
+    let language = match path.extension().and_then(|extension| extension.to_str()) {
+        // Extensions like C headers are shared with languages like C++, what
+        // the file declares tells them apart
+        Some(extension) => Language::from_extension_and_content(extension, content),
+        None => Language::Unknown,
+    };
+    if language != Language::Unknown {
+        return language;
+    }
+
+    let file_name = path.file_name().and_then(|name| name.to_str());
+    let language = file_name.map_or(Language::Unknown, Language::from_file_name);
+    if language != Language::Unknown {
+        return language;
+    }
+
+    let lines: Vec<&str> = content.lines().collect();
+    let first = lines.first().copied().unwrap_or_default();
+    let language = Language::from_shebang(first);
+    if language != Language::Unknown {
+        return language;
+    }
+
+    let ends = lines
+        .iter()
+        .take(MODELINES)
+        .chain(lines.iter().skip(MODELINES).rev().take(MODELINES));
+    if let Some(language) = ends
+        .map(|line| Language::from_modeline(line))
+        .find(|language| *language != Language::Unknown)
+    {
+        return language;
+    }
+
+    SYNTAXES
+        .find_syntax_by_first_line(first)
+        .and_then(|syntax| {
+            syntax
+                .file_extensions
+                .iter()
+                .map(|extension| Language::from_extension(extension))
+                .find(|language| *language != Language::Unknown)
+        })
+        .unwrap_or(Language::Unknown)
+}
+
+/// Whether `line` matches `syntax` declaring `name`, which is in the `name`
+/// group, or in syntaxes with several forms in one group per form.
+fn declares(syntax: &Regex, line: &str, name: &str) -> bool {
+    syntax.captures(line).is_some_and(|cap| {
+        cap.name("name")
+            .or_else(|| cap.iter().skip(1).flatten().next())
+            .is_some_and(|found| found.as_str() == name)
+    })
+}
+
+/// Line closing the first `open` from `start` on with `close`.
+fn delimited_end(lines: &[String], start: usize, open: &str, close: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (idx, line) in lines.iter().enumerate().skip(start) {
+        depth += line.matches(open).count() as isize;
+        depth -= line.matches(close).count() as isize;
+        if depth <= 0 && line.contains(close) {
+            return Some(idx);
+        }
+    }
+
+    None
+}
+
+/// Lines of the first `<name>` element, up to the tag closing it. Elements
+/// with the same name nested in it, like Vue's `<template #slot>`, are skipped.
+fn section_bounds(lines: &[String], name: &str) -> Option<(usize, usize)> {
+    let open = Regex::new(&format!(r"<{}(?:[\s>/]|$)", regex::escape(name))).unwrap();
+    let close = Regex::new(&format!(r"</{}\s*>", regex::escape(name))).unwrap();
+
+    let start = lines.iter().position(|line| open.is_match(line))?;
+    let mut depth = 0;
+    for (idx, line) in lines.iter().enumerate().skip(start) {
+        depth += open.find_iter(line).count() as isize;
+        depth -= close.find_iter(line).count() as isize;
+        if depth <= 0 {
+            return Some((start, idx));
+        }
+    }
+
+    None
+}
+
+/// Number of whitespace characters the line starts with.
+pub fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+/// Last non-empty line indented deeper than the line at `start`, or `start`
+/// if there are none.
+fn indented_block_end(lines: &[String], start: usize) -> usize {
+    let indent = indentation(&lines[start]);
+    let end = lines[start + 1..]
+        .iter()
+        .position(|line| !line.trim().is_empty() && indentation(line) <= indent)
+        .map_or(lines.len(), |offset| start + 1 + offset);
+
+    (start..end)
+        .rev()
+        .find(|idx| !lines[*idx].trim().is_empty())
+        .unwrap_or(start)
+}
+
 #[cfg(test)]
 mod tests {
     use super::{CommentType, Language, Rule};
     use std::path;
+    use std::sync::Arc;
 
     fn rule_from_language(lang: Language) -> Rule {
         let rust_path = path::Path::new("rust.rs");
@@ -69,17 +447,48 @@ mod tests {
         let typescript_path = path::Path::new("typescript.ts");
         let golang_path = path::Path::new("golang.go");
         let c_path = path::Path::new("c.c");
+        let cpp_path = path::Path::new("cpp.cpp");
+        let java_path = path::Path::new("Java.java");
+        let kotlin_path = path::Path::new("kotlin.kt");
+        let csharp_path = path::Path::new("CSharp.cs");
+        let ruby_path = path::Path::new("ruby.rb");
+        let lua_path = path::Path::new("lua.lua");
+        let elixir_path = path::Path::new("elixir.ex");
+        let bash_path = path::Path::new("bash.sh");
+        let jsx_path = path::Path::new("App.jsx");
+        let tsx_path = path::Path::new("App.tsx");
+        let vue_path = path::Path::new("App.vue");
+        let svelte_path = path::Path::new("App.svelte");
+        let makefile_path = path::Path::new("Makefile");
+        let haskell_path = path::Path::new("Main.hs");
+        let ocaml_path = path::Path::new("main.ml");
+        let fsharp_path = path::Path::new("Program.fs");
 
         match lang {
-            Language::Rust => Rule::new(&rust_path),
-            Language::Python => Rule::new(&python_path),
-            Language::Javascript => Rule::new(&javascript_path),
-            Language::Typescript => Rule::new(&typescript_path),
-            Language::Golang => Rule::new(&golang_path),
-            Language::C => Rule::new(&c_path),
+            Language::Rust => Rule::new(rust_path, ""),
+            Language::Python => Rule::new(python_path, ""),
+            Language::Javascript => Rule::new(javascript_path, ""),
+            Language::Typescript => Rule::new(typescript_path, ""),
+            Language::Golang => Rule::new(golang_path, ""),
+            Language::C => Rule::new(c_path, ""),
+            Language::Cpp => Rule::new(cpp_path, ""),
+            Language::Java => Rule::new(java_path, ""),
+            Language::Kotlin => Rule::new(kotlin_path, ""),
+            Language::CSharp => Rule::new(csharp_path, ""),
+            Language::Ruby => Rule::new(ruby_path, ""),
+            Language::Lua => Rule::new(lua_path, ""),
+            Language::Elixir => Rule::new(elixir_path, ""),
+            Language::Bash => Rule::new(bash_path, ""),
+            Language::Jsx => Rule::new(jsx_path, ""),
+            Language::Tsx => Rule::new(tsx_path, ""),
+            Language::Vue => Rule::new(vue_path, ""),
+            Language::Svelte => Rule::new(svelte_path, ""),
+            Language::Makefile => Rule::new(makefile_path, ""),
+            Language::Haskell => Rule::new(haskell_path, ""),
+            Language::OCaml => Rule::new(ocaml_path, ""),
+            Language::FSharp => Rule::new(fsharp_path, ""),
             _ => unreachable!(),
         }
-        .unwrap()
     }
 
     #[test]
@@ -90,8 +499,35 @@ mod tests {
             ("path/to/typescript.ts", Language::Typescript),
             ("javascript.module.js", Language::Javascript),
             ("golang-file.go", Language::Golang),
-            ("c_cpp.cc", Language::C),
+            ("c.c", Language::C),
+            ("c_cpp.cc", Language::Cpp),
+            ("include/vector.hpp", Language::Cpp),
+            ("src/main/java/App.java", Language::Java),
+            ("build.gradle.kts", Language::Kotlin),
+            ("Main.kt", Language::Kotlin),
+            ("Program.cs", Language::CSharp),
+            ("lib/tasks/db.rake", Language::Ruby),
+            ("init.lua", Language::Lua),
+            ("test/app_test.exs", Language::Elixir),
+            ("install.sh", Language::Bash),
+            (".zsh/aliases.zsh", Language::Bash),
             ("multiple.py.js", Language::Javascript),
+            ("rollup.config.mjs", Language::Javascript),
+            (".eslintrc.cjs", Language::Javascript),
+            ("src/App.jsx", Language::Jsx),
+            ("src/App.tsx", Language::Tsx),
+            ("src/components/Button.vue", Language::Vue),
+            ("src/routes/+page.svelte", Language::Svelte),
+            ("db/schema.sql", Language::Sql),
+            ("docker-compose.yml", Language::Yaml),
+            ("Cargo.toml", Language::Toml),
+            ("Cargo.lock", Language::Toml),
+            ("package.json", Language::Json),
+            ("notebooks/explore.ipynb", Language::Json),
+            ("README.md", Language::Markdown),
+            ("src/Main.hs", Language::Haskell),
+            ("lib/parser.mli", Language::OCaml),
+            ("build.fsx", Language::FSharp),
             ("unknown.unknown", Language::Unknown),
         ];
 
@@ -99,12 +535,73 @@ mod tests {
             let lang = lang.to_string();
 
             let path = path::Path::new(&lang);
-            let rule = Rule::new(&path).unwrap();
+            let rule = Rule::new(path, "");
 
             assert_eq!(rule.language, expected);
         }
     }
 
+    #[test]
+    fn detects_header_language() {
+        let headers = vec![
+            ("#include <stdio.h>\nint sum(int a, int b);\n", Language::C),
+            (
+                "#include <vector>\nint sum(std::vector<int> items);\n",
+                Language::Cpp,
+            ),
+            (
+                "namespace math {\nint sum(int a, int b);\n}\n",
+                Language::Cpp,
+            ),
+            ("class Point {\npublic:\n    int x;\n};\n", Language::Cpp),
+        ];
+
+        for (content, expected) in headers {
+            let path = path::Path::new("header.h");
+            assert_eq!(Rule::new(path, content).language, expected);
+        }
+    }
+
+    #[test]
+    fn detects_language_from_content() {
+        let files = vec![
+            ("Dockerfile", "FROM rust:1.80\n", Language::Dockerfile),
+            ("docker/Dockerfile.dev", "", Language::Dockerfile),
+            ("GNUmakefile", "all: build\n", Language::Makefile),
+            ("Gemfile", "source 'https://rubygems.org'\n", Language::Ruby),
+            ("/home/me/.zshrc", "", Language::Bash),
+            (
+                "bin/server",
+                "#!/usr/bin/env python3\nprint(1)\n",
+                Language::Python,
+            ),
+            (
+                "bin/build",
+                "#!/usr/bin/env -S node --harmony\n",
+                Language::Javascript,
+            ),
+            ("bin/setup", "#!/bin/bash -e\n", Language::Bash),
+            ("script", "puts 1\n# vim: set ft=ruby:\n", Language::Ruby),
+            (
+                "script",
+                "# -*- mode: python; coding: utf-8 -*-\n",
+                Language::Python,
+            ),
+            // Left to the syntaxes' first line patterns
+            ("bin/console", "#!/usr/bin/jruby\n", Language::Ruby),
+            ("env", "# -*- mode: shell-script -*-\n", Language::Bash),
+            ("-", "<?xml version=\"1.0\"?>\n", Language::Unknown),
+            // Extensions come first
+            ("run.py", "#!/bin/sh\n", Language::Python),
+            ("notes", "Nothing to see here\n", Language::Unknown),
+        ];
+
+        for (path, content, expected) in files {
+            let path = path::Path::new(path);
+            assert_eq!(Rule::new(path, content).language, expected, "{:?}", path);
+        }
+    }
+
     #[test]
     fn detects_function() {
         let functions = vec![
@@ -141,6 +638,81 @@ mod tests {
                 Language::Typescript,
                 false,
             ),
+            (
+                "export default function App() {",
+                "App",
+                Language::Jsx,
+                true,
+            ),
+            (
+                "export async function load({ params }) {",
+                "load",
+                Language::Svelte,
+                true,
+            ),
+            ("function* ids() {", "ids", Language::Javascript, true),
+            (
+                "const double = x => x * 2;",
+                "double",
+                Language::Javascript,
+                false,
+            ),
+            (
+                "export const Button: FC<Props> = ({ label }) => (",
+                "Button",
+                Language::Tsx,
+                true,
+            ),
+            (
+                "const List = function <T>(items: T[]): JSX.Element {",
+                "List",
+                Language::Tsx,
+                true,
+            ),
+            ("const count = ref(0)", "count", Language::Vue, false),
+            // Haskell, OCaml and F#
+            ("fact :: Int -> Int", "fact", Language::Haskell, true),
+            ("fact n = n * fact (n - 1)", "fact", Language::Haskell, true),
+            (
+                "  go acc (x:xs) | x > 0 = go (acc + x) xs",
+                "go",
+                Language::Haskell,
+                true,
+            ),
+            (
+                "  when (x == 1) $ print x",
+                "when",
+                Language::Haskell,
+                false,
+            ),
+            ("  greet \"x\"", "greet", Language::Haskell, false),
+            ("let rec fib n =", "fib", Language::OCaml, true),
+            (
+                "and is_odd n = n <> 0 && is_even (n - 1)",
+                "is_odd",
+                Language::OCaml,
+                true,
+            ),
+            ("val parse : string -> t", "parse", Language::OCaml, true),
+            ("let () = main ()", "main", Language::OCaml, false),
+            (
+                "let private helper (x: int) =",
+                "helper",
+                Language::FSharp,
+                true,
+            ),
+            (
+                "    member this.Area = w * h",
+                "Area",
+                Language::FSharp,
+                true,
+            ),
+            (
+                "    static member Create() =",
+                "Create",
+                Language::FSharp,
+                true,
+            ),
             // Golang
             ("func goFunc() int {", "goFunc", Language::Golang, true),
             (
@@ -160,15 +732,500 @@ mod tests {
             ("static void* func() {", "func", Language::C, true),
             ("CustomClass func()", "func", Language::C, true),
             ("void () {", "", Language::C, false),
+            ("int Class::method() {", "method", Language::C, false),
+            ("static void* func() {", "func", Language::Cpp, true),
+            (
+                "template <typename T> T max(const std::vector<T>& items) {",
+                "max",
+                Language::Cpp,
+                true,
+            ),
+            (
+                "Point::Point(int x, int y) : x_(x), y_(y) {",
+                "Point",
+                Language::Cpp,
+                true,
+            ),
+            ("    explicit Point(int x)", "Point", Language::Cpp, true),
+            ("Point::~Point() {", "~Point", Language::Cpp, true),
+            (
+                "    virtual ~Point() = default;",
+                "~Point",
+                Language::Cpp,
+                false,
+            ),
+            (
+                "bool Point::operator==(const Point& other) const noexcept {",
+                "operator==",
+                Language::Cpp,
+                true,
+            ),
+            (
+                "    Point& operator()(int i) override {",
+                "operator()",
+                Language::Cpp,
+                true,
+            ),
+            (
+                "auto Point::norm() const -> double {",
+                "norm",
+                Language::Cpp,
+                true,
+            ),
+            ("    return norm(x);", "norm", Language::Cpp, false),
+            // Java
+            (
+                "public static void main(String[] args) {",
+                "main",
+                Language::Java,
+                true,
+            ),
+            (
+                "    @Override public String toString() {",
+                "toString",
+                Language::Java,
+                true,
+            ),
+            (
+                "public static <T extends Comparable<T>> T max(List<? extends T> items) {",
+                "max",
+                Language::Java,
+                true,
+            ),
+            (
+                "protected Map<String, List<Integer>>[] groups() throws IOException",
+                "groups",
+                Language::Java,
+                true,
+            ),
+            (
+                "    public Point(int x, int y) {",
+                "Point",
+                Language::Java,
+                true,
+            ),
+            ("abstract void draw();", "draw", Language::Java, false),
+            ("        draw(canvas);", "draw", Language::Java, false),
+            // Kotlin
+            (
+                "fun main(args: Array<String>) {",
+                "main",
+                Language::Kotlin,
+                true,
+            ),
+            (
+                "    override suspend fun <T> fetch(id: Int): Result<T> {",
+                "fetch",
+                Language::Kotlin,
+                true,
+            ),
+            (
+                "fun <T> List<T>.second(): T = this[1]",
+                "second",
+                Language::Kotlin,
+                true,
+            ),
+            (
+                "    @JvmStatic fun create() = Point(0, 0)",
+                "create",
+                Language::Kotlin,
+                true,
+            ),
+            ("val fetch = fetch(1)", "fetch", Language::Kotlin, false),
+            // C#
+            (
+                "public static void Main(string[] args)",
+                "Main",
+                Language::CSharp,
+                true,
+            ),
+            (
+                "    [HttpGet] public async Task<IActionResult> Get(int id) {",
+                "Get",
+                Language::CSharp,
+                true,
+            ),
+            (
+                "private (int, string) Split<T>(T value) where T : class",
+                "Split",
+                Language::CSharp,
+                true,
+            ),
+            (
+                "public override string ToString() => Name;",
+                "ToString",
+                Language::CSharp,
+                true,
+            ),
+            ("var total = Sum(items);", "Sum", Language::CSharp, false),
+            // Ruby
+            (
+                "def ruby_method(a, b = 1)",
+                "ruby_method",
+                Language::Ruby,
+                true,
+            ),
+            ("  def self.empty?", "empty?", Language::Ruby, true),
+            ("  private def save! *args", "save!", Language::Ruby, true),
+            ("ruby_method(1)", "ruby_method", Language::Ruby, false),
+            // Lua
+            (
+                "local function lua_func(a, b)",
+                "lua_func",
+                Language::Lua,
+                true,
+            ),
+            ("function M.setup(opts)", "setup", Language::Lua, true),
+            (
+                "function Account:deposit(v)",
+                "deposit",
+                Language::Lua,
+                true,
+            ),
+            (
+                "M.handler = function(event)",
+                "handler",
+                Language::Lua,
+                true,
+            ),
+            ("lua_func(1, 2)", "lua_func", Language::Lua, false),
+            // Elixir
+            (
+                "  def elixir_fun(a, b) do",
+                "elixir_fun",
+                Language::Elixir,
+                true,
+            ),
+            (
+                "  defp valid?(x) when is_integer(x) do",
+                "valid?",
+                Language::Elixir,
+                true,
+            ),
+            ("  def one, do: 1", "one", Language::Elixir, true),
+            (
+                "    elixir_fun(1, 2)",
+                "elixir_fun",
+                Language::Elixir,
+                false,
+            ),
+            // Bash
+            ("bash_func() {", "bash_func", Language::Bash, true),
+            ("function bash_func {", "bash_func", Language::Bash, true),
+            ("function zsh-func() {", "zsh-func", Language::Bash, true),
+            ("bash_func arg", "bash_func", Language::Bash, false),
         ];
 
         for (line, name, lang, expected) in functions {
             let rule = rule_from_language(lang);
-            let result = rule.contains_function(&line.to_string(), &name.to_string());
+            let result = rule.contains_function(line, name);
             assert_eq!(result, expected);
         }
     }
 
+    #[test]
+    fn finds_function_bounds() {
+        let lines =
+            |lines: &[&str]| -> Vec<String> { lines.iter().map(|line| line.to_string()).collect() };
+
+        let rust = lines(&[
+            "fn one() {",
+            "}",
+            "fn two() -> u8 {",
+            "    if true { 1 } else {",
+            "        2",
+            "    }",
+            "}",
+        ]);
+        let rule = rule_from_language(Language::Rust);
+        assert_eq!(rule.find_function(&rust, "one"), Some((0, 1)));
+        assert_eq!(rule.find_function(&rust, "two"), Some((2, 6)));
+        assert_eq!(rule.find_function(&rust, "three"), None);
+
+        let python = lines(&["def one():", "    return 1", "", "def two():", "    pass"]);
+        let rule = rule_from_language(Language::Python);
+        assert_eq!(rule.find_function(&python, "one"), Some((0, 1)));
+        assert_eq!(rule.find_function(&python, "two"), Some((3, 4)));
+
+        let java = lines(&[
+            "public class Outer {",
+            "    public int size() {",
+            "        return 0;",
+            "    }",
+            "",
+            "    static class Inner {",
+            "        @Override",
+            "        @SuppressWarnings(\"unchecked\")",
+            "        public int size() {",
+            "            return 1;",
+            "        }",
+            "    }",
+            "}",
+        ]);
+        let rule = rule_from_language(Language::Java);
+        assert_eq!(rule.find_function(&java, "size"), Some((1, 3)));
+        assert_eq!(rule.find_function(&java, "Outer.Inner.size"), Some((6, 10)));
+        assert_eq!(rule.find_function(&java, "Inner.size"), Some((6, 10)));
+        assert_eq!(rule.find_function(&java, "Missing.size"), None);
+
+        let kotlin = lines(&[
+            "interface Shape {",
+            "    fun area(): Double",
+            "}",
+            "fun square(x: Int) =",
+            "    x * x",
+            "",
+            "fun main() {",
+            "}",
+        ]);
+        let rule = rule_from_language(Language::Kotlin);
+        assert_eq!(rule.find_function(&kotlin, "Shape.area"), Some((1, 1)));
+        assert_eq!(rule.find_function(&kotlin, "square"), Some((3, 4)));
+        assert_eq!(rule.find_function(&kotlin, "main"), Some((6, 7)));
+
+        let csharp = lines(&[
+            "public class Point",
+            "{",
+            "    [Obsolete]",
+            "    public int Sum()",
+            "    {",
+            "        return X + Y;",
+            "    }",
+            "    public override string ToString() => $\"{X}, {Y}\";",
+            "}",
+        ]);
+        let rule = rule_from_language(Language::CSharp);
+        assert_eq!(rule.find_function(&csharp, "Point.Sum"), Some((2, 6)));
+        assert_eq!(rule.find_function(&csharp, "ToString"), Some((7, 7)));
+
+        let ruby = lines(&[
+            "def compute(items)",
+            "  return [] if items.empty?",
+            "  total = if items.size > 1 then 2 else 1 end",
+            "  items.each do |item|",
+            "    while item.next do",
+            "      puts \"end\" # end",
+            "    end",
+            "  end",
+            "  range.end",
+            "end",
+            "def one; 1; end",
+        ]);
+        let rule = rule_from_language(Language::Ruby);
+        assert_eq!(rule.find_function(&ruby, "compute"), Some((0, 9)));
+        assert_eq!(rule.find_function(&ruby, "one"), Some((10, 10)));
+
+        let lua = lines(&[
+            "local function sum(items)",
+            "  local total = 0",
+            "  for _, item in ipairs(items) do",
+            "    if item then total = total + item end",
+            "  end",
+            "  repeat",
+            "    total = total - 1",
+            "  until total < 10 -- end",
+            "  return total",
+            "end",
+        ]);
+        let rule = rule_from_language(Language::Lua);
+        assert_eq!(rule.find_function(&lua, "sum"), Some((0, 9)));
+
+        let elixir = lines(&[
+            "  def run(list) do",
+            "    Enum.map(list, fn x -> x * 2 end)",
+            "    |> case do",
+            "      [] -> :end",
+            "      _ -> :ok",
+            "    end",
+            "  end",
+            "  def one, do: 1",
+        ]);
+        let rule = rule_from_language(Language::Elixir);
+        assert_eq!(rule.find_function(&elixir, "run"), Some((0, 6)));
+        assert_eq!(rule.find_function(&elixir, "one"), Some((7, 7)));
+
+        let cpp = lines(&[
+            "template <typename T>",
+            "[[nodiscard]]",
+            "T Stack<T>::pop() {",
+            "    return items_.back();",
+            "}",
+            "Stack::Stack(int size)",
+            "    : size_(size) {",
+            "}",
+        ]);
+        let rule = rule_from_language(Language::Cpp);
+        assert_eq!(rule.find_function(&cpp, "pop"), Some((0, 4)));
+        assert_eq!(rule.find_function(&cpp, "Stack"), Some((5, 7)));
+
+        let bash = lines(&[
+            "greet() {",
+            "  if true; then",
+            "    echo \"${1}\"",
+            "  fi",
+            "}",
+        ]);
+        let rule = rule_from_language(Language::Bash);
+        assert_eq!(rule.find_function(&bash, "greet"), Some((0, 4)));
+
+        // Every equation, after the signature and the pragma
+        let haskell = lines(&[
+            "{-# INLINE fact #-}",
+            "fact :: Int",
+            "     -> Int",
+            "fact 0 = 1",
+            "-- Anything else",
+            "fact n",
+            "  | n > 0 = n * fact (n - 1)",
+            "",
+            "main = print (fact 3)",
+        ]);
+        let rule = rule_from_language(Language::Haskell);
+        assert_eq!(rule.find_function(&haskell, "fact"), Some((0, 6)));
+        assert_eq!(rule.find_function(&haskell, "main"), Some((8, 8)));
+
+        let ocaml = lines(&[
+            "let rec even n =",
+            "  match n with",
+            "  | 0 -> true",
+            "  | n -> odd (n - 1)",
+            "and odd n = n <> 0 && even (n - 1)",
+        ]);
+        let rule = rule_from_language(Language::OCaml);
+        assert_eq!(rule.find_function(&ocaml, "even"), Some((0, 3)));
+        assert_eq!(rule.find_function(&ocaml, "odd"), Some((4, 4)));
+
+        let fsharp = lines(&[
+            "type Shape(w: float, h: float) =",
+            "    [<CompiledName(\"Area\")>]",
+            "    member this.Area =",
+            "        w * h",
+            "",
+            "    member this.Half = this.Area / 2.0",
+        ]);
+        let rule = rule_from_language(Language::FSharp);
+        assert_eq!(rule.find_function(&fsharp, "Area"), Some((1, 3)));
+
+        // Braces in the JSX do not end the component
+        let tsx = lines(&[
+            "export const Button: FC<Props> = ({ label }) => (",
+            "  <button onClick={() => alert(label)}>",
+            "    {label}",
+            "  </button>",
+            ");",
+        ]);
+        let rule = rule_from_language(Language::Tsx);
+        assert_eq!(rule.find_function(&tsx, "Button"), Some((0, 4)));
+
+        let vue = lines(&[
+            "<template>",
+            "  <Card>",
+            "    <template #header>{{ title }}</template>",
+            "  </Card>",
+            "</template>",
+            "",
+            "<script setup>",
+            "function close() {",
+            "  emit('close')",
+            "}",
+            "</script>",
+            "",
+            "<style scoped>",
+            ".card { margin: 0; }",
+            "</style>",
+        ]);
+        let rule = rule_from_language(Language::Vue);
+        assert_eq!(rule.find_function(&vue, "template"), Some((0, 4)));
+        assert_eq!(rule.find_function(&vue, "script"), Some((6, 10)));
+        assert_eq!(rule.find_function(&vue, "style"), Some((12, 14)));
+        assert_eq!(rule.find_function(&vue, "close"), Some((7, 9)));
+
+        let svelte = lines(&[
+            "<script>",
+            "  let count = 0;",
+            "</script>",
+            "<h1>{count}</h1>",
+        ]);
+        let rule = rule_from_language(Language::Svelte);
+        assert_eq!(rule.find_function(&svelte, "script"), Some((0, 2)));
+        assert_eq!(rule.find_function(&svelte, "template"), None);
+    }
+
+    #[test]
+    fn finds_functions_of_defined_languages() {
+        let languages = r#"
+            [[language]]
+            name = "Gleam"
+            extensions = ["gleam"]
+            function = '''^\s*(?:pub\s+)?fn\s+(?P<name>\w+)\('''
+            comments = { line = "//" }
+
+            [[language]]
+            name = "Groovy"
+            extensions = ["groovy"]
+            function = '''^\s*def\s+(?P<name>\w+)\s*\(.*\)\s*\{'''
+            class = '''^\s*class\s+(?P<name>\w+)'''
+            annotation = '''^\s*@'''
+            comments = { line = "//", block = ['/*', '*/'] }
+
+            [[language]]
+            name = "Ini"
+            extensions = ["ini"]
+            keys = "toml"
+        "#;
+        // Rules are made from the definitions directly, as loading them would
+        // change the languages of every other test
+        let mut rules = crate::definitions::parse(languages)
+            .unwrap()
+            .into_iter()
+            .map(|definition| Rule::with_definition(Arc::new(definition)));
+
+        let rule = rules.next().unwrap();
+        assert_eq!(rule.language, Language::Custom(String::from("Gleam")));
+
+        let gleam: Vec<String> = [
+            "// Adds one",
+            "pub fn add_one(x: Int) -> Int {",
+            "  x + 1",
+            "}",
+        ]
+        .iter()
+        .map(|line| line.to_string())
+        .collect();
+        assert_eq!(rule.find_function(&gleam, "add_one"), Some((1, 3)));
+        assert_eq!(
+            rule.contains_comment("// Adds one"),
+            Some(CommentType::SingleLine)
+        );
+
+        // Classes, annotations and keys come from the definition too
+        let rule = rules.next().unwrap();
+        let groovy: Vec<String> = [
+            "class Build {",
+            "  def clean() {",
+            "  }",
+            "  @Override",
+            "  def run() {",
+            "  }",
+            "}",
+            "def run() {",
+            "}",
+        ]
+        .iter()
+        .map(|line| line.to_string())
+        .collect();
+        assert_eq!(rule.find_function(&groovy, "Build.run"), Some((3, 5)));
+        assert_eq!(rule.find_function(&groovy, "run"), Some((3, 5)));
+
+        let rule = rules.next().unwrap();
+        assert!(rule.has_keys());
+        let ini: Vec<String> = ["[server]", "port = 80"]
+            .iter()
+            .map(|line| line.to_string())
+            .collect();
+        assert_eq!(rule.find_key(&ini, "server.port"), Some((1, 1)));
+    }
+
     #[test]
     fn detects_comment() {
         let comments = vec![
@@ -193,6 +1250,78 @@ mod tests {
                 Language::Typescript,
                 Some(CommentType::MultiLineComplete),
             ),
+            (
+                "/** Javadoc",
+                Language::Java,
+                Some(CommentType::MultiLineStart),
+            ),
+            (
+                "// Single line",
+                Language::Kotlin,
+                Some(CommentType::SingleLine),
+            ),
+            (
+                "/// <summary>",
+                Language::CSharp,
+                Some(CommentType::SingleLine),
+            ),
+            (
+                "# Single line",
+                Language::Ruby,
+                Some(CommentType::SingleLine),
+            ),
+            ("=begin", Language::Ruby, Some(CommentType::MultiLineStart)),
+            (
+                "-- Single line",
+                Language::Lua,
+                Some(CommentType::SingleLine),
+            ),
+            (
+                "--[[ Multi line start",
+                Language::Lua,
+                Some(CommentType::MultiLineStart),
+            ),
+            (
+                "--[[ Multi line complete ]]",
+                Language::Lua,
+                Some(CommentType::MultiLineComplete),
+            ),
+            (
+                "Multi line end ]]",
+                Language::Lua,
+                Some(CommentType::MultiLineEnd),
+            ),
+            (
+                "# Single line",
+                Language::Elixir,
+                Some(CommentType::SingleLine),
+            ),
+            ("IO.puts(1)", Language::Elixir, None),
+            (
+                "# Single line",
+                Language::Bash,
+                Some(CommentType::SingleLine),
+            ),
+            ("echo hi", Language::Bash, None),
+            (
+                "-- | Haddock",
+                Language::Haskell,
+                Some(CommentType::SingleLine),
+            ),
+            (
+                "{- Block -}",
+                Language::Haskell,
+                Some(CommentType::MultiLineComplete),
+            ),
+            ("{-# INLINE fact #-}", Language::Haskell, None),
+            (
+                "(* Block *)",
+                Language::OCaml,
+                Some(CommentType::MultiLineComplete),
+            ),
+            // OCaml has no single line comments
+            ("let x = 1", Language::OCaml, None),
+            ("/// Doc", Language::FSharp, Some(CommentType::SingleLine)),
             // Python
             (
                 "# Single line",
@@ -0,0 +1,164 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    static ref DOLLAR_QUOTE: Regex = Regex::new(r"^\$[A-Za-z_]*\$").unwrap();
+    static ref CREATE: Regex = Regex::new(
+        r#"(?is)^(?:\s*--[^\n]*\n|\s*/\*.*?\*/)*\s*CREATE\s+(?:OR\s+REPLACE\s+)?(?:(?:TEMP|TEMPORARY|MATERIALIZED|RECURSIVE|DEFINER\s*=\s*\S+)\s+)*(VIEW|FUNCTION|PROCEDURE)\s+(?:IF\s+NOT\s+EXISTS\s+)?([\w."`\[\]]+)"#
+    )
+    .unwrap();
+}
+
+/// Lines where each SQL statement in `lines` starts and ends, with the
+/// comments right above it. Statements end with a `;` that is not in a
+/// string, a quoted name, a dollar quoted body or a comment.
+pub fn statements(lines: &[String]) -> Vec<(usize, usize)> {
+    let mut bounds = Vec::new();
+    // First line of the statement being read and of the comments above it
+    let mut start = None;
+    let mut comments = None;
+    // What closes the string, name, body or comment the `;` would be in
+    let mut quote: Option<String> = None;
+    let mut last_code = 0;
+
+    for (idx, line) in lines.iter().enumerate() {
+        let mut comment = quote.as_deref() == Some("*/");
+        let mut code = false;
+        let mut pos = 0;
+        while pos < line.len() {
+            let rest = &line[pos..];
+            if let Some(close) = &quote {
+                match rest.find(close.as_str()) {
+                    Some(offset) => {
+                        pos += offset + close.len();
+                        quote = None;
+                    }
+                    None => pos = line.len(),
+                }
+                continue;
+            }
+
+            let next = rest.chars().next().unwrap();
+            if next.is_whitespace() {
+                pos += next.len_utf8();
+                continue;
+            } else if rest.starts_with("--") {
+                comment = true;
+                break;
+            } else if rest.starts_with("/*") {
+                comment = true;
+                quote = Some(String::from("*/"));
+                pos += 2;
+                continue;
+            }
+
+            code = true;
+            start.get_or_insert(idx);
+            if let Some(dollar) = DOLLAR_QUOTE.find(rest) {
+                quote = Some(dollar.as_str().to_string());
+                pos += dollar.end();
+                continue;
+            }
+            match next {
+                '\'' | '"' | '`' => quote = Some(next.to_string()),
+                '[' => quote = Some(String::from("]")),
+                ';' => {
+                    bounds.push((comments.or(start).unwrap_or(idx), idx));
+                    start = None;
+                    comments = None;
+                }
+                _ => (),
+            }
+            pos += next.len_utf8();
+        }
+
+        if code {
+            last_code = idx;
+        } else if start.is_none() {
+            // Comments belong to the statement right below them
+            if comment {
+                comments.get_or_insert(idx);
+            } else if line.trim().is_empty() {
+                comments = None;
+            }
+        }
+    }
+
+    // The last statement may go without a `;`
+    if let Some(start) = start {
+        bounds.push((comments.unwrap_or(start), last_code));
+    }
+
+    bounds
+}
+
+/// Lines of the statement creating the view, function or procedure `name`,
+/// with or without its schema. `kinds` are the kinds of objects to look for.
+pub fn find_create(lines: &[String], kinds: &[&str], name: &str) -> Option<(usize, usize)> {
+    statements(lines).into_iter().find(|(start, end)| {
+        let statement = lines[*start..=*end].join("\n");
+        CREATE.captures(&statement).is_some_and(|cap| {
+            let created = cap[2].replace(['"', '`', '[', ']'], "");
+            let unqualified = created.rsplit('.').next().unwrap_or_default();
+            kinds.iter().any(|kind| kind.eq_ignore_ascii_case(&cap[1]))
+                && (created == name || unqualified == name)
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_create, statements};
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn splits_statements() {
+        let schema = lines(
+            "-- Users of the app\n\
+             CREATE TABLE users (\n\
+             \x20 name TEXT DEFAULT 'a;b' /* not ; the end */\n\
+             );\n\
+             \n\
+             INSERT INTO users VALUES ('x'); INSERT INTO users VALUES ('y');\n\
+             /* Counts them;\n\
+             \x20  all of them */\n\
+             CREATE FUNCTION count_users() RETURNS bigint AS $body$\n\
+             \x20 SELECT count(*) FROM users;\n\
+             $body$ LANGUAGE sql;\n\
+             \n\
+             SELECT 1",
+        );
+
+        assert_eq!(
+            statements(&schema),
+            vec![(0, 3), (5, 5), (5, 5), (6, 10), (12, 12)]
+        );
+    }
+
+    #[test]
+    fn finds_views_and_functions() {
+        let schema = lines(
+            "CREATE TABLE users (name TEXT);\n\
+             CREATE OR REPLACE VIEW public.\"active_users\" AS\n\
+             \x20 SELECT * FROM users;\n\
+             CREATE FUNCTION active_users() RETURNS int AS $$ SELECT 1 $$ LANGUAGE sql;",
+        );
+
+        assert_eq!(
+            find_create(&schema, &["VIEW"], "active_users"),
+            Some((1, 2))
+        );
+        assert_eq!(
+            find_create(&schema, &["VIEW"], "public.active_users"),
+            Some((1, 2))
+        );
+        assert_eq!(
+            find_create(&schema, &["FUNCTION", "PROCEDURE"], "active_users"),
+            Some((3, 3))
+        );
+        assert_eq!(find_create(&schema, &["VIEW"], "users"), None);
+    }
+}
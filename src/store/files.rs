@@ -0,0 +1,629 @@
+use sqlite::{self, State};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::{sql, Problem, SnippetStore};
+use crate::bookmark::Bookmark;
+use crate::language::Language;
+use crate::utils;
+
+const INDEX_FILE_NAME: &str = "index.sql";
+
+/// Version 0 had a single `bookmarks` table keyed by content id, so two
+/// bookmarks could not share the same content. Version 1 keys bookmarks by
+/// name and keeps reference counted content in `blobs`. Version 2 ids are
+/// computed with `utils::content_hash`. Version 3 adds the description and
+/// tags of the bookmarks, and the `history` of their content. Version 4 adds
+/// their notes and related urls. Version 5 adds the file and lines the
+/// content was captured from. Version 6 adds when the bookmarks were last
+/// updated.
+const SCHEMA_VERSION: i64 = 6;
+
+/// Row of the index, the content lives in the file named after `id`.
+struct Row {
+    id: String,
+    name: String,
+    lang: String,
+    description: String,
+    tags: String,
+    notes: String,
+    urls: String,
+    source: String,
+    origin: String,
+    updated: String,
+}
+
+/// Default store layout: a sqlite index with one row per bookmark, and the
+/// content of each bookmark in a file named after its id. Bookmarks with the
+/// same content share the file.
+pub struct FileStore {
+    root: PathBuf,
+}
+
+impl FileStore {
+    /// Opens the store at `root`, if one has been initialized there.
+    pub fn open<P: AsRef<Path>>(root: P) -> Option<Self> {
+        let root = root.as_ref();
+        if !root.join(INDEX_FILE_NAME).exists() {
+            return None;
+        }
+
+        Some(FileStore {
+            root: root.to_path_buf(),
+        })
+    }
+
+    pub fn init<P: AsRef<Path>>(root: P) -> io::Result<Self> {
+        fs::create_dir_all(&root)?;
+        let store = FileStore {
+            root: root.as_ref().to_path_buf(),
+        };
+
+        let conn = store.get_connection()?;
+        Self::create_tables(&conn)?;
+        sql::create_history_table(&conn)?;
+        sql::set_schema_version(&conn, SCHEMA_VERSION)?;
+
+        Ok(store)
+    }
+
+    fn create_tables(conn: &sqlite::Connection) -> io::Result<()> {
+        sql::execute(
+            conn,
+            "CREATE TABLE bookmarks (name TEXT PRIMARY KEY, id TEXT NOT NULL, lang TEXT, \
+             description TEXT NOT NULL DEFAULT '', tags TEXT NOT NULL DEFAULT '', \
+             notes TEXT NOT NULL DEFAULT '', urls TEXT NOT NULL DEFAULT '', \
+             source TEXT NOT NULL DEFAULT '', origin TEXT NOT NULL DEFAULT '', \
+             updated TEXT NOT NULL DEFAULT '');",
+            &[],
+        )?;
+        sql::execute(
+            conn,
+            "CREATE TABLE blobs (id TEXT PRIMARY KEY, refcount INTEGER NOT NULL);",
+            &[],
+        )
+    }
+
+    /// Opens the index, migrating it to the current schema if needed.
+    fn get_connection(&self) -> io::Result<sqlite::Connection> {
+        let conn = match sqlite::open(self.root.join(INDEX_FILE_NAME)) {
+            Ok(conn) => conn,
+            Err(e) => return Err(io::Error::other(e)),
+        };
+
+        if sql::has_table(&conn, "bookmarks")? && sql::schema_version(&conn)? < SCHEMA_VERSION {
+            let stale = super::transaction(&conn, || self.migrate(&conn))?;
+
+            // Only removed once the index no longer points to them
+            for id in stale {
+                let _ = fs::remove_file(self.get_path(&id));
+            }
+        }
+
+        Ok(conn)
+    }
+
+    /// Migrates the index to the current schema, returning the ids of the
+    /// content files left unused.
+    fn migrate(&self, conn: &sqlite::Connection) -> io::Result<Vec<String>> {
+        let version = sql::schema_version(conn)?;
+
+        // References are counted over the history, so it has to exist first
+        if version < 3 {
+            sql::create_history_table(conn)?;
+        }
+
+        // Version 0 is moved to the current tables, later ones get the
+        // metadata columns added since
+        if version < 1 {
+            Self::migrate_v0(conn)?;
+        } else {
+            sql::add_metadata_columns(conn, version)?;
+        }
+
+        let mut stale = Vec::new();
+        if version < 2 {
+            stale = self.migrate_v1(conn)?;
+        }
+
+        sql::set_schema_version(conn, SCHEMA_VERSION)?;
+        Ok(stale)
+    }
+
+    /// Moves the bookmarks to the current tables.
+    fn migrate_v0(conn: &sqlite::Connection) -> io::Result<()> {
+        sql::execute(conn, "ALTER TABLE bookmarks RENAME TO bookmarks_v0;", &[])?;
+        Self::create_tables(conn)?;
+
+        for statement in [
+            "INSERT INTO bookmarks (name, id, lang) SELECT name, id, lang FROM bookmarks_v0;",
+            "INSERT INTO blobs (id, refcount) SELECT id, COUNT(*) FROM bookmarks GROUP BY id;",
+            "DROP TABLE bookmarks_v0;",
+        ] {
+            sql::execute(conn, statement, &[])?;
+        }
+
+        Ok(())
+    }
+
+    /// Rehashes the content with the current content hash. The content is
+    /// copied to its new file, the old ones are returned to be removed after
+    /// the index has been updated.
+    fn migrate_v1(&self, conn: &sqlite::Connection) -> io::Result<Vec<String>> {
+        let mut stale = Vec::new();
+        for id in sql::refcounts(conn)?.into_keys() {
+            let lines = match utils::read_lines(self.get_path(&id)) {
+                Ok(lines) => lines.collect::<io::Result<Vec<String>>>()?,
+                // Left for fsck to report
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+
+            let actual = utils::content_hash(&lines);
+            if actual == id {
+                continue;
+            }
+            if !self.get_path(&actual).exists() {
+                super::write_atomically(&self.get_path(&actual), &utils::join_lines(&lines))?;
+            }
+
+            sql::execute(
+                conn,
+                "UPDATE bookmarks SET id = ? WHERE id = ?;",
+                &[&actual, &id],
+            )?;
+            sql::recount(conn, &id)?;
+            sql::recount(conn, &actual)?;
+            stale.push(id);
+        }
+
+        Ok(stale)
+    }
+
+    fn get_path(&self, id: &str) -> PathBuf {
+        self.root.join(id)
+    }
+
+    /// Runs a `SELECT id, name, lang, description, tags, notes, urls, source,
+    /// origin, updated` query over the index.
+    fn rows(&self, statement: &str, params: &[&str]) -> io::Result<Vec<Row>> {
+        let conn = self.get_connection()?;
+        let mut statement = sql::prepare(&conn, statement, params)?;
+
+        let mut rows = Vec::new();
+        while statement.next().map_err(io::Error::other)? == State::Row {
+            rows.push(Row {
+                id: statement.read(0).map_err(io::Error::other)?,
+                name: statement.read(1).map_err(io::Error::other)?,
+                lang: statement.read(2).map_err(io::Error::other)?,
+                description: statement.read(3).map_err(io::Error::other)?,
+                tags: statement.read(4).map_err(io::Error::other)?,
+                notes: statement.read(5).map_err(io::Error::other)?,
+                urls: statement.read(6).map_err(io::Error::other)?,
+                source: statement.read(7).map_err(io::Error::other)?,
+                origin: statement.read(8).map_err(io::Error::other)?,
+                updated: statement.read(9).map_err(io::Error::other)?,
+            });
+        }
+
+        Ok(rows)
+    }
+
+    fn load(&self, row: Row, history: Vec<String>) -> io::Result<Bookmark> {
+        let lines = match utils::read_lines(self.get_path(&row.id)) {
+            Ok(lines) => lines,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(super::missing_content(&row.name))
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(Bookmark {
+            id: row.id,
+            name: row.name,
+            content: lines.collect::<io::Result<_>>()?,
+            lang: Language::from_extension(&row.lang),
+            description: row.description,
+            tags: sql::split_list(&row.tags),
+            notes: row.notes,
+            urls: sql::split_list(&row.urls),
+            history,
+            source: row.source,
+            origin: utils::parse_ranges(&row.origin),
+            updated: row.updated.parse().unwrap_or(0),
+        })
+    }
+
+    /// Inserts `bookmark`, replacing the one with the same name if `replace`
+    /// is set, and writes its content unless it is already stored.
+    fn save(&self, bookmark: &Bookmark, replace: bool) -> io::Result<()> {
+        let path = self.get_path(&bookmark.id);
+        let existed = path.exists();
+        let conn = self.get_connection()?;
+
+        let result = super::transaction(&conn, || {
+            let (is_new, unreferenced) = match replace {
+                true => sql::replace_bookmark(&conn, bookmark)?,
+                false => (sql::insert_bookmark(&conn, bookmark)?, Vec::new()),
+            };
+
+            // Content already stored for another bookmark is shared, not rewritten
+            if is_new || !existed {
+                super::write_atomically(&path, &utils::join_lines(&bookmark.content))?;
+            }
+            Ok(unreferenced)
+        });
+
+        match result {
+            Ok(unreferenced) => self.remove_content(&conn, &unreferenced),
+            Err(e) => {
+                // Do not leave a content file behind that no row points to
+                if !existed {
+                    let _ = fs::remove_file(&path);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Removes the content files of `ids` that no blob refers to anymore.
+    fn remove_content(&self, conn: &sqlite::Connection, ids: &[String]) -> io::Result<()> {
+        for id in ids {
+            if sql::blob_exists(conn, id)? {
+                continue;
+            }
+            match fs::remove_file(self.get_path(id)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fixes a single problem found by `fsck`.
+    fn repair(&self, conn: &sqlite::Connection, problem: &Problem) -> io::Result<()> {
+        match problem {
+            Problem::OrphanContent(id) => super::transaction(conn, || {
+                sql::execute(conn, "DELETE FROM blobs WHERE id = ?;", &[id])?;
+                match fs::remove_file(self.get_path(id)) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+                    _ => Ok(()),
+                }
+            }),
+            Problem::MissingContent(name) => {
+                let unreferenced = super::transaction(conn, || sql::remove_bookmark(conn, name))?;
+                self.remove_content(conn, &unreferenced)
+            }
+            Problem::MissingVersion { name, id } => super::transaction(conn, || {
+                sql::execute(
+                    conn,
+                    "DELETE FROM history WHERE name = ? AND id = ?;",
+                    &[name, id],
+                )?;
+                sql::recount(conn, id)
+            }),
+            Problem::HashMismatch { id, actual, .. } => super::transaction(conn, || {
+                for statement in [
+                    "UPDATE bookmarks SET id = ? WHERE id = ?;",
+                    "UPDATE history SET id = ? WHERE id = ?;",
+                ] {
+                    sql::execute(conn, statement, &[actual, id])?;
+                }
+                sql::recount(conn, id)?;
+                sql::recount(conn, actual)?;
+
+                if self.get_path(actual).exists() {
+                    fs::remove_file(self.get_path(id))
+                } else {
+                    fs::rename(self.get_path(id), self.get_path(actual))
+                }
+            }),
+            Problem::RefcountMismatch { id, .. } => sql::recount(conn, id),
+        }
+    }
+}
+
+impl SnippetStore for FileStore {
+    fn create(&mut self, bookmark: &Bookmark) -> io::Result<()> {
+        super::check_name(&bookmark.name)?;
+        if self.exists(&bookmark.name)? {
+            return Err(super::already_exists(&bookmark.name));
+        }
+
+        self.save(bookmark, false)
+    }
+
+    fn update(&mut self, bookmark: &Bookmark) -> io::Result<()> {
+        self.save(bookmark, true)
+    }
+
+    fn delete(&mut self, name: &str) -> io::Result<()> {
+        let conn = self.get_connection()?;
+        let unreferenced = super::transaction(&conn, || sql::remove_bookmark(&conn, name))?;
+        self.remove_content(&conn, &unreferenced)
+    }
+
+    fn rename(&mut self, name: &str, new_name: &str) -> io::Result<()> {
+        super::check_name(new_name)?;
+        if !self.exists(name)? {
+            return Err(super::not_found(name));
+        }
+        if self.exists(new_name)? {
+            return Err(super::already_exists(new_name));
+        }
+
+        let conn = self.get_connection()?;
+        super::transaction(&conn, || sql::rename_bookmark(&conn, name, new_name))
+    }
+
+    fn get(&self, name: &str) -> io::Result<Option<Bookmark>> {
+        let row = self
+            .rows(
+                "SELECT id, name, lang, description, tags, notes, urls, source, origin, updated FROM bookmarks WHERE name = ?;",
+                &[name],
+            )?
+            .pop();
+
+        match row {
+            Some(row) => {
+                let history = sql::history_of(&self.get_connection()?, name)?;
+                Ok(Some(self.load(row, history)?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn all(&self) -> io::Result<Vec<Bookmark>> {
+        let mut history = sql::history(&self.get_connection()?)?;
+        self.rows(
+            "SELECT id, name, lang, description, tags, notes, urls, source, origin, updated FROM bookmarks;",
+            &[],
+        )?
+        .into_iter()
+        .map(|row| {
+            let versions = history.remove(&row.name).unwrap_or_default();
+            self.load(row, versions)
+        })
+        .collect()
+    }
+
+    fn exists(&self, name: &str) -> io::Result<bool> {
+        let num_matches = self
+            .rows(
+                "SELECT id, name, lang, description, tags, notes, urls, source, origin, updated FROM bookmarks WHERE name = ?;",
+                &[name],
+            )?
+            .len();
+
+        if num_matches > 1 {
+            return Err(io::Error::other(
+                "More than one bookmark with the same name",
+            ));
+        }
+
+        Ok(num_matches == 1)
+    }
+
+    fn fsck(&mut self, repair: bool) -> io::Result<Vec<Problem>> {
+        let conn = self.get_connection()?;
+        let rows = self.rows(
+            "SELECT id, name, lang, description, tags, notes, urls, source, origin, updated FROM bookmarks;",
+            &[],
+        )?;
+        let history = sql::history(&conn)?;
+        let refcounts = sql::refcounts(&conn)?;
+
+        // Bookmarks pointing to each content, and whether it is their current
+        // content or a previous version
+        let mut references: BTreeMap<&str, Vec<(&str, bool)>> = BTreeMap::new();
+        for row in &rows {
+            references
+                .entry(&row.id)
+                .or_default()
+                .push((&row.name, true));
+        }
+        for (name, ids) in &history {
+            for id in ids {
+                references.entry(id).or_default().push((name, false));
+            }
+        }
+
+        // Content files and blobs no bookmark points to
+        let mut orphans: BTreeSet<String> = refcounts
+            .keys()
+            .filter(|id| !references.contains_key(id.as_str()))
+            .cloned()
+            .collect();
+        for file in fs::read_dir(&self.root)? {
+            let file_name = file?.file_name().to_string_lossy().to_string();
+
+            // Skip the index and the journal sqlite keeps next to it
+            if file_name.starts_with(INDEX_FILE_NAME) {
+                continue;
+            }
+            if !references.contains_key(file_name.as_str()) {
+                orphans.insert(file_name);
+            }
+        }
+
+        let mut problems: Vec<Problem> = orphans.into_iter().map(Problem::OrphanContent).collect();
+
+        for (id, holders) in &references {
+            let (stored, actual) = (
+                refcounts.get(*id).copied().unwrap_or(0),
+                holders.len() as i64,
+            );
+            if stored != actual {
+                problems.push(Problem::RefcountMismatch {
+                    id: id.to_string(),
+                    stored,
+                    actual,
+                });
+            }
+        }
+
+        for (id, holders) in &references {
+            let lines = match utils::read_lines(self.get_path(id)) {
+                Ok(lines) => lines.collect::<io::Result<Vec<String>>>()?,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    problems.extend(
+                        holders
+                            .iter()
+                            .map(|(name, current)| super::missing(name, id, *current)),
+                    );
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+
+            let actual = utils::content_hash(&lines);
+            if actual != *id {
+                problems.push(Problem::HashMismatch {
+                    name: holders[0].0.to_string(),
+                    id: id.to_string(),
+                    actual,
+                });
+            }
+        }
+
+        if repair {
+            for problem in &problems {
+                self.repair(&conn, problem)?;
+            }
+        }
+
+        Ok(problems)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FileStore;
+    use crate::store::tests::bookmark;
+    use crate::store::{Problem, SnippetStore};
+    use std::{fs, io};
+
+    #[test]
+    fn migrates_schema_v0() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut first = bookmark("first", &["fn first() {", "}"]);
+        // Bookmarks saved before version 6 have no update date
+        first.updated = 0;
+
+        let conn = sqlite::open(tmp.path().join("index.sql")).unwrap();
+        conn.execute("CREATE TABLE bookmarks (id TEXT PRIMARY KEY, name TEXT, lang TEXT);")
+            .unwrap();
+        // Ids used to be computed with an older hash
+        conn.execute("INSERT INTO bookmarks (id, name, lang) VALUES ('old', 'first', 'rs');")
+            .unwrap();
+        fs::write(tmp.path().join("old"), "fn first() {\n}\n").unwrap();
+
+        let mut store = FileStore::open(tmp.path()).unwrap();
+        assert_eq!(store.get("first").unwrap(), Some(first));
+        assert!(!tmp.path().join("old").exists());
+        assert!(store.fsck(false).unwrap().is_empty());
+
+        // Content can be shared once migrated
+        store
+            .create(&bookmark("alias", &["fn first() {", "}"]))
+            .unwrap();
+        assert!(store.fsck(false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn migrates_schema_v2() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut first = bookmark("first", &["fn first() {", "}"]);
+        // Bookmarks saved before version 6 have no update date
+        first.updated = 0;
+
+        let conn = sqlite::open(tmp.path().join("index.sql")).unwrap();
+        conn.execute(
+            "CREATE TABLE bookmarks (name TEXT PRIMARY KEY, id TEXT NOT NULL, lang TEXT);",
+        )
+        .unwrap();
+        conn.execute("CREATE TABLE blobs (id TEXT PRIMARY KEY, refcount INTEGER NOT NULL);")
+            .unwrap();
+        conn.execute(format!(
+            "INSERT INTO bookmarks VALUES ('first', '{0}', 'rs'); \
+             INSERT INTO blobs VALUES ('{0}', 1); PRAGMA user_version = 2;",
+            first.id
+        ))
+        .unwrap();
+        fs::write(tmp.path().join(&first.id), "fn first() {\n}\n").unwrap();
+
+        let mut store = FileStore::open(tmp.path()).unwrap();
+        assert_eq!(store.get("first").unwrap(), Some(first.clone()));
+
+        let mut edited = first;
+        edited.set_content(&[String::from("fn first() {}")]);
+        edited.tags = vec![String::from("migrated")];
+        store.update(&edited).unwrap();
+        assert_eq!(store.get("first").unwrap(), Some(edited));
+        assert!(store.fsck(false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rolls_back_failed_create() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut store = FileStore::init(tmp.path()).unwrap();
+
+        // A directory in the way makes writing the content fail after the
+        // row has been inserted
+        let first = bookmark("first", &["content"]);
+        fs::create_dir(tmp.path().join(&first.id)).unwrap();
+        assert!(store.create(&first).is_err());
+        fs::remove_dir(tmp.path().join(&first.id)).unwrap();
+
+        assert!(!store.exists("first").unwrap());
+        assert!(store.fsck(false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn detects_and_repairs_problems() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut store = FileStore::init(tmp.path()).unwrap();
+
+        let missing = bookmark("missing", &["missing"]);
+        let tampered = bookmark("tampered", &["original", "content"]);
+        let changed = bookmark("tampered", &["changed"]);
+        store.create(&missing).unwrap();
+        store.create(&tampered).unwrap();
+        store
+            .create(&bookmark("alias", &["original", "content"]))
+            .unwrap();
+
+        fs::remove_file(tmp.path().join(&missing.id)).unwrap();
+        fs::write(tmp.path().join(&tampered.id), "changed\n").unwrap();
+        fs::write(tmp.path().join("orphan"), "orphan\n").unwrap();
+
+        let err = store.get("missing").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        let mut problems = store.fsck(true).unwrap();
+        problems.sort_by_key(|problem| problem.to_string());
+        assert_eq!(
+            problems,
+            vec![
+                Problem::MissingContent(String::from("missing")),
+                Problem::HashMismatch {
+                    name: String::from("tampered"),
+                    id: tampered.id,
+                    actual: changed.id.clone(),
+                },
+                Problem::OrphanContent(String::from("orphan")),
+            ]
+        );
+
+        assert!(store.fsck(false).unwrap().is_empty());
+        assert!(!store.exists("missing").unwrap());
+        for name in ["tampered", "alias"] {
+            let repaired = store.get(name).unwrap().unwrap();
+            assert_eq!(repaired.id, changed.id);
+            assert_eq!(repaired.content, vec!["changed"]);
+        }
+    }
+}
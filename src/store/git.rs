@@ -0,0 +1,782 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::{Problem, SnippetStore};
+use crate::bookmark::Bookmark;
+use crate::language::Language;
+use crate::utils;
+
+const INDEX_FILE_NAME: &str = "index.toml";
+const DEFAULT_BRANCH: &str = "main";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Entry {
+    id: String,
+    name: String,
+    lang: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    description: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    notes: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    urls: Vec<String>,
+    /// Ids of the previous versions of the content, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    history: Vec<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    source: String,
+    /// Lines of `source` the content comes from, like `3-5,9`
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    origin: String,
+    /// Seconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "is_zero")]
+    updated: u64,
+}
+
+impl Entry {
+    fn new(bookmark: &Bookmark) -> Self {
+        Entry {
+            id: bookmark.id.clone(),
+            name: bookmark.name.clone(),
+            lang: bookmark.lang.to_extension(),
+            description: bookmark.description.clone(),
+            tags: bookmark.tags.clone(),
+            notes: bookmark.notes.clone(),
+            urls: bookmark.urls.clone(),
+            history: bookmark.history.clone(),
+            source: bookmark.source.clone(),
+            origin: utils::format_ranges(&bookmark.origin),
+            updated: bookmark.updated,
+        }
+    }
+
+    /// Ids of all the content the entry refers to, the current one first.
+    fn ids(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.id).chain(&self.history)
+    }
+}
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Index {
+    /// Version of `utils::content_hash` the ids were computed with, missing
+    /// in indexes written before it was versioned.
+    #[serde(default)]
+    version: u32,
+    #[serde(default)]
+    bookmarks: Vec<Entry>,
+}
+
+impl Index {
+    fn parse(content: &str) -> io::Result<Self> {
+        toml::from_str(content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn by_name(self) -> BTreeMap<String, Entry> {
+        self.bookmarks
+            .into_iter()
+            .map(|entry| (entry.name.clone(), entry))
+            .collect()
+    }
+}
+
+/// Two collaborators saved different content under the same bookmark name.
+/// The local version is kept when syncing.
+#[derive(Debug, PartialEq)]
+pub struct Conflict {
+    pub name: String,
+    pub local_id: String,
+    pub remote_id: String,
+}
+
+/// Bookmark store kept as plain text files plus an `index.toml` with the
+/// metadata, inside a git repository so it can be shared with `sync`.
+pub struct GitStore {
+    root: PathBuf,
+}
+
+impl GitStore {
+    /// Opens the store at `root`, if one has been initialized there.
+    pub fn open<P: AsRef<Path>>(root: P) -> Option<Self> {
+        let root = root.as_ref();
+        if !root.join(INDEX_FILE_NAME).exists() {
+            return None;
+        }
+
+        let store = GitStore {
+            root: root.to_path_buf(),
+        };
+        // Indexes saved with an older content hash are rehashed once, here,
+        // so that reading bookmarks has no side effects. Should it fail, like
+        // in a read-only clone, bookmarks are read with their old ids and the
+        // next change tries again
+        let _ = store.index_to_update();
+        Some(store)
+    }
+
+    /// Creates a new store at `root`. An existing git repository is reused,
+    /// otherwise a new one is created with `main` as its branch.
+    pub fn init<P: AsRef<Path>>(root: P, remote: Option<&str>) -> io::Result<Self> {
+        let root = root.as_ref();
+        if root.join(INDEX_FILE_NAME).exists() {
+            let err_msg = format!("Bookmark store already exists in {}", root.display());
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, err_msg));
+        }
+
+        fs::create_dir_all(root)?;
+        let store = GitStore {
+            root: root.to_path_buf(),
+        };
+
+        if !root.join(".git").exists() {
+            store.git(&["init", "--quiet"])?;
+            let head = format!("refs/heads/{}", DEFAULT_BRANCH);
+            store.git(&["symbolic-ref", "HEAD", &head])?;
+        }
+
+        if let Some(url) = remote {
+            store.git(&["remote", "add", "origin", url])?;
+        }
+
+        store.write_index(&BTreeMap::new())?;
+        if let Err(e) = store.commit("Initialize bookmark store") {
+            // Without the index the directory is not considered a store, so
+            // init can be retried after fixing the git configuration
+            fs::remove_file(root.join(INDEX_FILE_NAME))?;
+            return Err(e);
+        }
+
+        Ok(store)
+    }
+
+    fn git(&self, args: &[&str]) -> io::Result<String> {
+        let stdout = self.git_output(args)?;
+        Ok(String::from_utf8_lossy(&stdout).trim_end().to_string())
+    }
+
+    /// Runs git and returns its output untouched.
+    fn git_output(&self, args: &[&str]) -> io::Result<Vec<u8>> {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(&self.root)
+            .args(args)
+            .output()?;
+
+        if !output.status.success() {
+            let err_msg = format!(
+                "git {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            return Err(io::Error::other(err_msg));
+        }
+
+        Ok(output.stdout)
+    }
+
+    fn commit(&self, message: &str) -> io::Result<()> {
+        self.git(&["add", "--all"])?;
+        self.git(&["commit", "--quiet", "--allow-empty", "-m", message])?;
+        Ok(())
+    }
+
+    /// Runs `f` and commits its changes. If anything fails the working tree
+    /// is reset to the last commit, which is always a consistent store.
+    fn transaction<T, F>(&self, message: &str, f: F) -> io::Result<T>
+    where
+        F: FnOnce() -> io::Result<T>,
+    {
+        let result = f().and_then(|value| {
+            self.commit(message)?;
+            Ok(value)
+        });
+
+        if result.is_err() {
+            let _ = self.git(&["reset", "--quiet", "--hard", "HEAD"]);
+            let _ = self.git(&["clean", "--quiet", "--force"]);
+        }
+        result
+    }
+
+    /// Reads the index as it is, without changing anything.
+    fn read_index(&self) -> io::Result<BTreeMap<String, Entry>> {
+        let content = fs::read_to_string(self.root.join(INDEX_FILE_NAME))?;
+        Ok(Index::parse(&content)?.by_name())
+    }
+
+    /// Reads the index to change it, rehashing the content first if it was
+    /// saved with an older content hash.
+    fn index_to_update(&self) -> io::Result<BTreeMap<String, Entry>> {
+        let content = fs::read_to_string(self.root.join(INDEX_FILE_NAME))?;
+        let index = Index::parse(&content)?;
+        if index.version < utils::CONTENT_HASH_VERSION {
+            return self.migrate(index.by_name());
+        }
+
+        Ok(index.by_name())
+    }
+
+    /// Renames every content file after its current content hash and commits
+    /// the updated index.
+    fn migrate(&self, mut entries: BTreeMap<String, Entry>) -> io::Result<BTreeMap<String, Entry>> {
+        self.transaction("Rehash bookmark content", || {
+            let mut rehashed: BTreeMap<String, String> = BTreeMap::new();
+            for entry in entries.values_mut() {
+                if let Some(actual) = rehashed.get(&entry.id) {
+                    entry.id = actual.clone();
+                    continue;
+                }
+
+                let lines = match utils::read_lines(self.root.join(&entry.id)) {
+                    Ok(lines) => lines.collect::<io::Result<Vec<String>>>()?,
+                    // Left for fsck to report
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                    Err(e) => return Err(e),
+                };
+
+                let actual = utils::content_hash(&lines);
+                if !self.root.join(&actual).exists() {
+                    fs::rename(self.root.join(&entry.id), self.root.join(&actual))?;
+                }
+                rehashed.insert(entry.id.clone(), actual.clone());
+                entry.id = actual;
+            }
+
+            self.write_index(&entries)?;
+            self.remove_unreferenced(&entries)?;
+            Ok(entries)
+        })
+    }
+
+    /// Reads the index as it was at `revision`, empty if it did not exist.
+    /// Entries saved with an older content hash get their current id, and the
+    /// returned sources map it to the file holding the content at `revision`.
+    fn read_index_at(
+        &self,
+        revision: &str,
+    ) -> io::Result<(BTreeMap<String, Entry>, BTreeMap<String, String>)> {
+        let object = format!("{}:{}", revision, INDEX_FILE_NAME);
+        let index = match self.git(&["show", &object]) {
+            Ok(content) => Index::parse(&content)?,
+            Err(_) => return Ok((BTreeMap::new(), BTreeMap::new())),
+        };
+
+        let version = index.version;
+        let mut entries = index.by_name();
+        let mut sources = BTreeMap::new();
+        if version >= utils::CONTENT_HASH_VERSION {
+            return Ok((entries, sources));
+        }
+
+        for entry in entries.values_mut() {
+            let object = format!("{}:{}", revision, entry.id);
+            let content = match self.git_output(&["show", &object]) {
+                Ok(content) => content,
+                Err(_) => continue,
+            };
+
+            let lines: Vec<String> = String::from_utf8_lossy(&content)
+                .lines()
+                .map(String::from)
+                .collect();
+            let actual = utils::content_hash(&lines);
+            sources.insert(actual.clone(), std::mem::replace(&mut entry.id, actual));
+        }
+
+        Ok((entries, sources))
+    }
+
+    fn write_index(&self, entries: &BTreeMap<String, Entry>) -> io::Result<()> {
+        let index = Index {
+            version: utils::CONTENT_HASH_VERSION,
+            bookmarks: entries.values().cloned().collect(),
+        };
+        let content = toml::to_string(&index).map_err(io::Error::other)?;
+
+        super::write_atomically(&self.root.join(INDEX_FILE_NAME), &content)
+    }
+
+    /// Removes content files that are no longer referenced by any entry.
+    fn remove_unreferenced(&self, entries: &BTreeMap<String, Entry>) -> io::Result<()> {
+        let referenced: BTreeSet<&String> = entries.values().flat_map(Entry::ids).collect();
+
+        for file in fs::read_dir(&self.root)? {
+            let file = file?;
+            let file_name = file.file_name().to_string_lossy().to_string();
+            if file_name.starts_with('.') || file_name == INDEX_FILE_NAME {
+                continue;
+            }
+
+            if file.file_type()?.is_file() && !referenced.contains(&file_name) {
+                fs::remove_file(file.path())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn load(&self, entry: &Entry) -> io::Result<Bookmark> {
+        let lines = match utils::read_lines(self.root.join(&entry.id)) {
+            Ok(lines) => lines,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(super::missing_content(&entry.name))
+            }
+            Err(e) => return Err(e),
+        };
+        let content = lines.collect::<io::Result<_>>()?;
+
+        Ok(Bookmark {
+            id: entry.id.clone(),
+            name: entry.name.clone(),
+            lang: Language::from_extension(&entry.lang),
+            content,
+            description: entry.description.clone(),
+            tags: entry.tags.clone(),
+            notes: entry.notes.clone(),
+            urls: entry.urls.clone(),
+            history: entry.history.clone(),
+            source: entry.source.clone(),
+            origin: utils::parse_ranges(&entry.origin),
+            updated: entry.updated,
+        })
+    }
+
+    fn merge(&self, remote: &str, remote_ref: &str) -> io::Result<Vec<Conflict>> {
+        let is_ancestor =
+            |a: &str, b: &str| self.git(&["merge-base", "--is-ancestor", a, b]).is_ok();
+
+        if is_ancestor(remote_ref, "HEAD") {
+            return Ok(Vec::new());
+        }
+        if is_ancestor("HEAD", remote_ref) {
+            self.git(&["merge", "--quiet", "--ff-only", remote_ref])?;
+            return Ok(Vec::new());
+        }
+
+        let base = match self.git(&["merge-base", "HEAD", remote_ref]) {
+            Ok(base) => self.read_index_at(&base)?.0,
+            Err(_) => BTreeMap::new(),
+        };
+        let local = self.index_to_update()?;
+        let (theirs, sources) = self.read_index_at(remote_ref)?;
+
+        let mut conflicts = Vec::new();
+        let mut merged = BTreeMap::new();
+
+        let names: BTreeSet<&String> = local.keys().chain(theirs.keys()).collect();
+        for name in names {
+            let (ours, other, ancestor) = (local.get(name), theirs.get(name), base.get(name));
+
+            let entry = if ours == other || other == ancestor {
+                ours
+            } else if ours == ancestor {
+                other
+            } else {
+                match (ours, other) {
+                    (Some(ours), Some(other)) => {
+                        conflicts.push(Conflict {
+                            name: name.clone(),
+                            local_id: ours.id.clone(),
+                            remote_id: other.id.clone(),
+                        });
+                        Some(ours)
+                    }
+                    // Deleted on one side and changed on the other, keep the change
+                    (ours, other) => ours.or(other),
+                }
+            };
+
+            if let Some(entry) = entry {
+                merged.insert(name.clone(), entry.clone());
+            }
+        }
+
+        // Record the merge with both parents, but build the resulting tree by
+        // hand so that the index is merged by entry and not by text lines.
+        self.transaction(&format!("Sync bookmarks with '{}'", remote), || {
+            self.git(&[
+                "merge",
+                "--quiet",
+                "--no-commit",
+                "--no-ff",
+                "--allow-unrelated-histories",
+                "-s",
+                "ours",
+                remote_ref,
+            ])?;
+
+            for id in merged.values().flat_map(Entry::ids) {
+                if self.root.join(id).exists() {
+                    continue;
+                }
+
+                // Content saved with an older hash is renamed after checkout
+                match sources.get(id) {
+                    Some(source) => {
+                        self.git(&["checkout", remote_ref, "--", source])?;
+                        fs::rename(self.root.join(source), self.root.join(id))?;
+                    }
+                    None => {
+                        self.git(&["checkout", remote_ref, "--", id])?;
+                    }
+                }
+            }
+
+            self.write_index(&merged)?;
+            self.remove_unreferenced(&merged)
+        })?;
+
+        Ok(conflicts)
+    }
+}
+
+impl SnippetStore for GitStore {
+    fn create(&mut self, bookmark: &Bookmark) -> io::Result<()> {
+        super::check_name(&bookmark.name)?;
+        let mut entries = self.index_to_update()?;
+        if entries.contains_key(&bookmark.name) {
+            return Err(super::already_exists(&bookmark.name));
+        }
+
+        entries.insert(bookmark.name.clone(), Entry::new(bookmark));
+
+        self.transaction(&format!("Add bookmark '{}'", bookmark.name), || {
+            let content = utils::join_lines(&bookmark.content);
+            super::write_atomically(&self.root.join(&bookmark.id), &content)?;
+            self.write_index(&entries)
+        })
+    }
+
+    fn update(&mut self, bookmark: &Bookmark) -> io::Result<()> {
+        let mut entries = self.index_to_update()?;
+        if !entries.contains_key(&bookmark.name) {
+            return Err(super::not_found(&bookmark.name));
+        }
+
+        entries.insert(bookmark.name.clone(), Entry::new(bookmark));
+
+        self.transaction(&format!("Update bookmark '{}'", bookmark.name), || {
+            let content = utils::join_lines(&bookmark.content);
+            super::write_atomically(&self.root.join(&bookmark.id), &content)?;
+            self.write_index(&entries)?;
+            self.remove_unreferenced(&entries)
+        })
+    }
+
+    fn delete(&mut self, name: &str) -> io::Result<()> {
+        let mut entries = self.index_to_update()?;
+        if entries.remove(name).is_none() {
+            return Err(super::not_found(name));
+        }
+
+        self.transaction(&format!("Delete bookmark '{}'", name), || {
+            self.write_index(&entries)?;
+            self.remove_unreferenced(&entries)
+        })
+    }
+
+    fn rename(&mut self, name: &str, new_name: &str) -> io::Result<()> {
+        super::check_name(new_name)?;
+        let mut entries = self.index_to_update()?;
+        if !entries.contains_key(name) {
+            return Err(super::not_found(name));
+        }
+        if entries.contains_key(new_name) {
+            return Err(super::already_exists(new_name));
+        }
+
+        let mut entry = entries.remove(name).unwrap();
+        entry.name = new_name.to_string();
+        entries.insert(entry.name.clone(), entry);
+
+        let message = format!("Rename bookmark '{}' to '{}'", name, new_name);
+        self.transaction(&message, || self.write_index(&entries))
+    }
+
+    fn get(&self, name: &str) -> io::Result<Option<Bookmark>> {
+        match self.read_index()?.get(name) {
+            Some(entry) => Ok(Some(self.load(entry)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn all(&self) -> io::Result<Vec<Bookmark>> {
+        self.read_index()?
+            .values()
+            .map(|entry| self.load(entry))
+            .collect()
+    }
+
+    /// Pulls the bookmarks from `remote`, merges them with the local ones and
+    /// pushes the result back. Both sides are merged entry by entry against
+    /// their common ancestor, so additions and deletions from each side are
+    /// kept. Names that were changed on both sides are reported as conflicts.
+    fn sync(&mut self, remote: &str) -> io::Result<Vec<Conflict>> {
+        let branch = self.git(&["symbolic-ref", "--short", "HEAD"])?;
+        let remote_ref = format!("refs/remotes/{}/{}", remote, branch);
+
+        self.git(&["fetch", "--quiet", remote])?;
+
+        let mut conflicts = Vec::new();
+        if self
+            .git(&["rev-parse", "--verify", "--quiet", &remote_ref])
+            .is_ok()
+        {
+            conflicts = self.merge(remote, &remote_ref)?;
+        }
+
+        let refspec = format!("HEAD:refs/heads/{}", branch);
+        self.git(&["push", "--quiet", remote, &refspec])?;
+
+        Ok(conflicts)
+    }
+
+    fn fsck(&mut self, repair: bool) -> io::Result<Vec<Problem>> {
+        let mut entries = self.index_to_update()?;
+        let referenced: BTreeSet<String> = entries.values().flat_map(Entry::ids).cloned().collect();
+
+        let mut problems = Vec::new();
+        for file in fs::read_dir(&self.root)? {
+            let file_name = file?.file_name().to_string_lossy().to_string();
+            if file_name.starts_with('.') || file_name == INDEX_FILE_NAME {
+                continue;
+            }
+            if !referenced.contains(&file_name) {
+                problems.push(Problem::OrphanContent(file_name));
+            }
+        }
+
+        let mut checked = BTreeSet::new();
+        for entry in entries.values() {
+            for id in entry.ids() {
+                let lines = match utils::read_lines(self.root.join(id)) {
+                    Ok(lines) => lines.collect::<io::Result<Vec<String>>>()?,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                        problems.push(super::missing(&entry.name, id, id == &entry.id));
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                };
+
+                // Content shared between bookmarks only has to be checked once
+                if !checked.insert(id) {
+                    continue;
+                }
+
+                let actual = utils::content_hash(&lines);
+                if &actual != id {
+                    problems.push(Problem::HashMismatch {
+                        name: entry.name.clone(),
+                        id: id.clone(),
+                        actual,
+                    });
+                }
+            }
+        }
+
+        if repair && !problems.is_empty() {
+            self.transaction("Repair bookmark store", || {
+                for problem in &problems {
+                    match problem {
+                        Problem::OrphanContent(file) => fs::remove_file(self.root.join(file))?,
+                        Problem::MissingContent(name) => {
+                            entries.remove(name);
+                        }
+                        Problem::MissingVersion { name, id } => {
+                            if let Some(entry) = entries.get_mut(name) {
+                                entry.history.retain(|version| version != id);
+                            }
+                        }
+                        Problem::HashMismatch { id, actual, .. } => {
+                            fs::rename(self.root.join(id), self.root.join(actual))?;
+                            for entry in entries.values_mut() {
+                                let ids = std::iter::once(&mut entry.id).chain(&mut entry.history);
+                                for version in ids.filter(|version| *version == id) {
+                                    *version = actual.clone();
+                                }
+                            }
+                        }
+                        // References are derived from the index, they cannot disagree
+                        Problem::RefcountMismatch { .. } => (),
+                    }
+                }
+                self.write_index(&entries)
+            })?;
+        }
+
+        Ok(problems)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Conflict, GitStore};
+    use crate::store::tests::{bookmark, git_store};
+    use crate::store::SnippetStore;
+    use std::path::Path;
+    use std::process::Command;
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    fn setup() -> (tempfile::TempDir, GitStore, GitStore) {
+        let tmp = tempfile::tempdir().unwrap();
+        let remote = tmp.path().join("remote.git");
+        let (alice, bob) = (tmp.path().join("alice"), tmp.path().join("bob"));
+        std::fs::create_dir_all(&alice).unwrap();
+        std::fs::create_dir_all(&bob).unwrap();
+
+        git(
+            tmp.path(),
+            &["init", "--quiet", "--bare", remote.to_str().unwrap()],
+        );
+
+        let alice = git_store(&alice, Some(&remote));
+        let bob = git_store(&bob, Some(&remote));
+        (tmp, alice, bob)
+    }
+
+    fn names(store: &GitStore) -> Vec<String> {
+        store.all().unwrap().into_iter().map(|bk| bk.name).collect()
+    }
+
+    #[test]
+    fn removes_unreferenced_content() {
+        let (_tmp, mut store, _) = setup();
+
+        let main = bookmark("main", &["fn main() {", "}"]);
+        store.create(&main).unwrap();
+        assert!(store.root.join(&main.id).exists());
+
+        store.delete("main").unwrap();
+        assert!(store.get("main").unwrap().is_none());
+        assert!(!store.root.join(&main.id).exists());
+    }
+
+    #[test]
+    fn rehashes_content_of_old_index() {
+        let (_tmp, store, _) = setup();
+
+        // Ids used to be computed with an older hash, and the index had no version
+        std::fs::write(store.root.join("old"), "fn main() {\n}\n").unwrap();
+        std::fs::write(
+            store.root.join("index.toml"),
+            "[[bookmarks]]\nid = \"old\"\nname = \"main\"\nlang = \"rs\"\n",
+        )
+        .unwrap();
+        git(&store.root, &["add", "--all"]);
+        git(&store.root, &["commit", "--quiet", "-m", "Old index"]);
+
+        // Reading leaves the store as it is
+        let head = store.git(&["rev-parse", "HEAD"]).unwrap();
+        assert_eq!(store.get("main").unwrap().unwrap().id, "old");
+        assert_eq!(store.all().unwrap().len(), 1);
+        assert!(store.root.join("old").exists());
+        assert_eq!(store.git(&["rev-parse", "HEAD"]).unwrap(), head);
+
+        // Opening it rehashes the content once
+        let store = GitStore::open(&store.root).unwrap();
+        let mut main = bookmark("main", &["fn main() {", "}"]);
+        // Entries written before update dates were recorded have none
+        main.updated = 0;
+        assert_eq!(store.get("main").unwrap(), Some(main));
+        assert!(!store.root.join("old").exists());
+        assert_eq!(store.git(&["status", "--porcelain"]).unwrap(), "");
+    }
+
+    #[test]
+    fn renames_and_copies_bookmarks() {
+        let (_tmp, mut store, _) = setup();
+
+        let main = bookmark("main", &["fn main() {", "}"]);
+        store.create(&main).unwrap();
+        store.rename("main", "rust/main").unwrap();
+        store.copy("rust/main", "main").unwrap();
+
+        assert_eq!(names(&store), vec!["main", "rust/main"]);
+        assert_eq!(store.get("main").unwrap(), Some(main));
+        assert_eq!(store.git(&["status", "--porcelain"]).unwrap(), "");
+    }
+
+    #[test]
+    fn keeps_previous_versions() {
+        let (_tmp, mut store, _) = setup();
+
+        let main = bookmark("main", &["fn main() {", "}"]);
+        store.create(&main).unwrap();
+
+        let mut edited = main.clone();
+        edited.set_content(&[String::from("fn main() {}")]);
+        edited.description = String::from("Entry point");
+        store.update(&edited).unwrap();
+
+        assert_eq!(store.get("main").unwrap(), Some(edited));
+        assert!(store.root.join(&main.id).exists());
+        assert!(store.fsck(false).unwrap().is_empty());
+
+        store.delete("main").unwrap();
+        assert!(!store.root.join(&main.id).exists());
+    }
+
+    #[test]
+    fn syncs_bookmarks_between_stores() {
+        let (_tmp, mut alice, mut bob) = setup();
+
+        alice.create(&bookmark("first", &["a"])).unwrap();
+        assert!(alice.sync("origin").unwrap().is_empty());
+
+        let second = bookmark("second", &["b"]);
+        bob.create(&second).unwrap();
+        assert!(bob.sync("origin").unwrap().is_empty());
+        assert_eq!(names(&bob), vec!["first", "second"]);
+
+        assert!(alice.sync("origin").unwrap().is_empty());
+        assert_eq!(names(&alice), vec!["first", "second"]);
+        assert_eq!(alice.get("second").unwrap(), Some(second));
+
+        alice.delete("first").unwrap();
+        alice.sync("origin").unwrap();
+        bob.sync("origin").unwrap();
+        assert_eq!(names(&bob), vec!["second"]);
+    }
+
+    #[test]
+    fn reports_name_conflicts() {
+        let (_tmp, mut alice, mut bob) = setup();
+
+        let ours = bookmark("shared", &["bob"]);
+        let theirs = bookmark("shared", &["alice"]);
+
+        alice.create(&theirs).unwrap();
+        alice.sync("origin").unwrap();
+        bob.create(&ours).unwrap();
+
+        let conflicts = bob.sync("origin").unwrap();
+        assert_eq!(
+            conflicts,
+            vec![Conflict {
+                name: String::from("shared"),
+                local_id: ours.id.clone(),
+                remote_id: theirs.id,
+            }]
+        );
+        assert_eq!(bob.get("shared").unwrap(), Some(ours));
+    }
+}
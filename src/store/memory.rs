@@ -0,0 +1,70 @@
+use std::collections::BTreeMap;
+use std::io;
+
+use super::SnippetStore;
+use crate::bookmark::Bookmark;
+
+/// Store that only lives as long as the value, meant for tests.
+#[derive(Default)]
+pub struct MemoryStore {
+    bookmarks: BTreeMap<String, Bookmark>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+}
+
+impl SnippetStore for MemoryStore {
+    fn create(&mut self, bookmark: &Bookmark) -> io::Result<()> {
+        super::check_name(&bookmark.name)?;
+        if self.bookmarks.contains_key(&bookmark.name) {
+            return Err(super::already_exists(&bookmark.name));
+        }
+
+        self.bookmarks
+            .insert(bookmark.name.clone(), bookmark.clone());
+        Ok(())
+    }
+
+    fn update(&mut self, bookmark: &Bookmark) -> io::Result<()> {
+        match self.bookmarks.get_mut(&bookmark.name) {
+            Some(existing) => {
+                *existing = bookmark.clone();
+                Ok(())
+            }
+            None => Err(super::not_found(&bookmark.name)),
+        }
+    }
+
+    fn delete(&mut self, name: &str) -> io::Result<()> {
+        match self.bookmarks.remove(name) {
+            Some(_) => Ok(()),
+            None => Err(super::not_found(name)),
+        }
+    }
+
+    fn rename(&mut self, name: &str, new_name: &str) -> io::Result<()> {
+        super::check_name(new_name)?;
+        if !self.bookmarks.contains_key(name) {
+            return Err(super::not_found(name));
+        }
+        if self.bookmarks.contains_key(new_name) {
+            return Err(super::already_exists(new_name));
+        }
+
+        let mut bookmark = self.bookmarks.remove(name).unwrap();
+        bookmark.name = new_name.to_string();
+        self.bookmarks.insert(bookmark.name.clone(), bookmark);
+        Ok(())
+    }
+
+    fn get(&self, name: &str) -> io::Result<Option<Bookmark>> {
+        Ok(self.bookmarks.get(name).cloned())
+    }
+
+    fn all(&self) -> io::Result<Vec<Bookmark>> {
+        Ok(self.bookmarks.values().cloned().collect())
+    }
+}
@@ -0,0 +1,497 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::bookmark::Bookmark;
+
+mod files;
+mod git;
+mod memory;
+mod sql;
+mod sqlite;
+
+pub use files::FileStore;
+pub use git::{Conflict, GitStore};
+pub use memory::MemoryStore;
+pub use sqlite::SqliteStore;
+
+pub const DEFAULT_PATH: &str = ".capture";
+
+/// Layouts a bookmark store can be created with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    /// Sqlite index with the content of each bookmark in its own file
+    Files,
+    /// Single sqlite file with the content stored in BLOB columns
+    Sqlite,
+    /// Plain text files and a TOML index inside a git repository
+    Git,
+}
+
+/// Storage for bookmarks. Every command goes through this trait, so the
+/// backends can be swapped without the commands knowing about them.
+pub trait SnippetStore {
+    /// Saves a new bookmark, failing if its name is invalid or one with the
+    /// same name already exists.
+    fn create(&mut self, bookmark: &Bookmark) -> io::Result<()>;
+
+    /// Replaces the bookmark with the same name, including its metadata and
+    /// history, failing if it does not exist.
+    fn update(&mut self, bookmark: &Bookmark) -> io::Result<()>;
+
+    fn delete(&mut self, name: &str) -> io::Result<()>;
+
+    /// Gives the bookmark `name` the name `new_name`, keeping its content and
+    /// everything else recorded about it. Fails if `new_name` is invalid.
+    fn rename(&mut self, name: &str, new_name: &str) -> io::Result<()>;
+
+    /// Saves a copy of the bookmark `name` as `new_name`, sharing its content
+    /// with the original.
+    fn copy(&mut self, name: &str, new_name: &str) -> io::Result<()> {
+        let mut bookmark = match self.get(name)? {
+            Some(bookmark) => bookmark,
+            None => return Err(not_found(name)),
+        };
+        if self.exists(new_name)? {
+            return Err(already_exists(new_name));
+        }
+
+        bookmark.name = new_name.to_string();
+        self.create(&bookmark)
+    }
+
+    fn get(&self, name: &str) -> io::Result<Option<Bookmark>>;
+
+    fn all(&self) -> io::Result<Vec<Bookmark>>;
+
+    fn exists(&self, name: &str) -> io::Result<bool> {
+        Ok(self.get(name)?.is_some())
+    }
+
+    /// Merges the bookmarks with the ones in `remote`, returning the names
+    /// that could not be merged.
+    fn sync(&mut self, _remote: &str) -> io::Result<Vec<Conflict>> {
+        let err_msg = "Bookmark store is not git-backed, create one with 'capture init --git'";
+        Err(io::Error::new(io::ErrorKind::Unsupported, err_msg))
+    }
+
+    /// Looks for inconsistencies between the index and the content of the
+    /// bookmarks, fixing them if `repair` is set.
+    fn fsck(&mut self, _repair: bool) -> io::Result<Vec<Problem>> {
+        Ok(Vec::new())
+    }
+}
+
+/// Inconsistency found by [`SnippetStore::fsck`].
+#[derive(Debug, PartialEq)]
+pub enum Problem {
+    /// Stored content that no bookmark points to
+    OrphanContent(String),
+    /// Bookmark whose content file does not exist
+    MissingContent(String),
+    /// Previous version of a bookmark whose content file does not exist
+    MissingVersion { name: String, id: String },
+    /// Bookmark whose content does not hash to its id
+    HashMismatch {
+        name: String,
+        id: String,
+        actual: String,
+    },
+    /// Content whose reference count differs from the bookmarks pointing to it
+    RefcountMismatch {
+        id: String,
+        stored: i64,
+        actual: i64,
+    },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::OrphanContent(id) => write!(f, "orphaned content '{}'", id),
+            Problem::MissingContent(name) => {
+                write!(f, "bookmark '{}' has no content file", name)
+            }
+            Problem::MissingVersion { name, id } => write!(
+                f,
+                "version '{}' of bookmark '{}' has no content file",
+                id, name
+            ),
+            Problem::HashMismatch { name, id, actual } => write!(
+                f,
+                "bookmark '{}' has id '{}' but its content hashes to '{}'",
+                name, id, actual
+            ),
+            Problem::RefcountMismatch { id, stored, actual } => write!(
+                f,
+                "content '{}' has {} reference(s) recorded but {} bookmark(s) point to it",
+                id, stored, actual
+            ),
+        }
+    }
+}
+
+/// Checks that `name` can be used as a bookmark name. Names can be grouped
+/// in namespaces separated by `/`, like `rust/iterators/windows`.
+pub fn check_name(name: &str) -> io::Result<()> {
+    if name.split('/').any(|part| part.trim().is_empty()) {
+        let err_msg = format!(
+            "Invalid bookmark name '{}', namespaces and names cannot be empty",
+            name
+        );
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, err_msg));
+    }
+
+    Ok(())
+}
+
+/// Problem reported for a bookmark whose content `id` is missing, depending
+/// on whether it is its `current` content or a previous version.
+pub(crate) fn missing(name: &str, id: &str, current: bool) -> Problem {
+    if current {
+        Problem::MissingContent(name.to_string())
+    } else {
+        Problem::MissingVersion {
+            name: name.to_string(),
+            id: id.to_string(),
+        }
+    }
+}
+
+pub(crate) fn already_exists(name: &str) -> io::Error {
+    let err_msg = format!("Bookmark with name: '{}' already exists", name);
+    io::Error::new(io::ErrorKind::AlreadyExists, err_msg)
+}
+
+pub(crate) fn not_found(name: &str) -> io::Error {
+    let err_msg = format!("Bookmark with name: '{}' does not exist.", name);
+    io::Error::new(io::ErrorKind::NotFound, err_msg)
+}
+
+pub(crate) fn missing_content(name: &str) -> io::Error {
+    let err_msg = format!(
+        "Content of bookmark '{}' is missing, run 'capture fsck --repair'",
+        name
+    );
+    io::Error::new(io::ErrorKind::NotFound, err_msg)
+}
+
+/// Writes `content` to `path` through a temporary file that is renamed into
+/// place, so the file is either fully written or not there at all.
+pub(crate) fn write_atomically(path: &Path, content: &str) -> io::Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+
+    let result = fs::File::create(&tmp_path)
+        .and_then(|mut file| {
+            file.write_all(content.as_bytes())?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&tmp_path, path));
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+/// Runs `f` inside a sqlite transaction, rolling it back if `f` fails.
+pub(crate) fn transaction<T, F>(conn: &::sqlite::Connection, f: F) -> io::Result<T>
+where
+    F: FnOnce() -> io::Result<T>,
+{
+    conn.execute("BEGIN;").map_err(io::Error::other)?;
+
+    let result = f().and_then(|value| {
+        conn.execute("COMMIT;").map_err(io::Error::other)?;
+        Ok(value)
+    });
+
+    if result.is_err() {
+        let _ = conn.execute("ROLLBACK;");
+    }
+    result
+}
+
+/// Opens the bookmark store in the current directory.
+pub fn open() -> io::Result<Box<dyn SnippetStore>> {
+    open_at(DEFAULT_PATH)
+}
+
+/// Opens the bookmark store at `root`, detecting its backend from the files in it.
+pub fn open_at<P: AsRef<Path>>(root: P) -> io::Result<Box<dyn SnippetStore>> {
+    let root = root.as_ref();
+
+    if let Some(store) = GitStore::open(root) {
+        return Ok(Box::new(store));
+    }
+    if let Some(store) = SqliteStore::open(root) {
+        return Ok(Box::new(store));
+    }
+    if let Some(store) = FileStore::open(root) {
+        return Ok(Box::new(store));
+    }
+
+    let err_msg = format!(
+        "No bookmark store found in '{}', create one with 'capture init'",
+        root.display()
+    );
+    Err(io::Error::new(io::ErrorKind::NotFound, err_msg))
+}
+
+/// Creates a bookmark store in the current directory.
+pub fn setup(backend: Backend, remote: Option<&str>) -> io::Result<()> {
+    if open_at(DEFAULT_PATH).is_ok() {
+        let err_msg = format!("Bookmark store already exists in '{}'", DEFAULT_PATH);
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, err_msg));
+    }
+
+    match backend {
+        Backend::Files => FileStore::init(DEFAULT_PATH).map(|_| ()),
+        Backend::Sqlite => SqliteStore::init(DEFAULT_PATH).map(|_| ()),
+        Backend::Git => GitStore::init(DEFAULT_PATH, remote).map(|_| ()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FileStore, GitStore, MemoryStore, SnippetStore, SqliteStore};
+    use crate::bookmark::Bookmark;
+    use crate::language::Language;
+    use std::io;
+    use std::path::Path;
+    use std::process::Command;
+
+    /// Bookmark of Rust code, the fixture of the tests of every backend.
+    pub(super) fn bookmark(name: &str, content: &[&str]) -> Bookmark {
+        let content: Vec<String> = content.iter().map(|line| line.to_string()).collect();
+        Bookmark::new(name, &content, Language::Rust)
+    }
+
+    /// Git store in `dir` pushing to `remote`, with a local identity so that
+    /// commits work regardless of the global git configuration.
+    pub(super) fn git_store(dir: &Path, remote: Option<&Path>) -> GitStore {
+        for args in [
+            &["init", "--quiet"][..],
+            &["symbolic-ref", "HEAD", "refs/heads/main"],
+            &["config", "user.name", "capture"],
+            &["config", "user.email", "capture@localhost"],
+        ] {
+            let status = Command::new("git")
+                .arg("-C")
+                .arg(dir)
+                .args(args)
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success(), "git {:?} failed", args);
+        }
+
+        GitStore::init(dir, remote.map(|remote| remote.to_str().unwrap())).unwrap()
+    }
+
+    /// An empty store of every backend, in directories inside `tmp`.
+    fn stores(tmp: &Path) -> Vec<Box<dyn SnippetStore>> {
+        let dir = |name: &str| {
+            let dir = tmp.join(name);
+            std::fs::create_dir_all(&dir).unwrap();
+            dir
+        };
+
+        vec![
+            Box::new(MemoryStore::new()),
+            Box::new(FileStore::init(dir("files")).unwrap()),
+            Box::new(SqliteStore::init(dir("sqlite")).unwrap()),
+            Box::new(git_store(&dir("git"), None)),
+        ]
+    }
+
+    #[test]
+    fn creates_and_deletes_bookmarks() {
+        let tmp = tempfile::tempdir().unwrap();
+        for mut store in stores(tmp.path()) {
+            let first = bookmark("first", &["fn first() {", "    1", "}"]);
+            let second = bookmark("second", &["fn second() {}"]);
+            assert!(store.all().unwrap().is_empty());
+            assert!(!store.exists("first").unwrap());
+
+            store.create(&first).unwrap();
+            store.create(&second).unwrap();
+            let err = store.create(&first).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+            let err = store.create(&bookmark("rust//first", &["x"])).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+            assert!(store.exists("first").unwrap());
+            assert_eq!(store.get("first").unwrap(), Some(first.clone()));
+            assert_eq!(store.get("missing").unwrap(), None);
+            let mut names: Vec<String> =
+                store.all().unwrap().into_iter().map(|bk| bk.name).collect();
+            names.sort();
+            assert_eq!(names, vec!["first", "second"]);
+
+            store.delete("first").unwrap();
+            assert_eq!(store.get("first").unwrap(), None);
+            let err = store.delete("first").unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::NotFound);
+            assert_eq!(store.get("second").unwrap(), Some(second));
+
+            let err = store.update(&first).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::NotFound);
+            assert!(store.fsck(false).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn shares_content_between_bookmarks() {
+        let tmp = tempfile::tempdir().unwrap();
+        for mut store in stores(tmp.path()) {
+            // Bookmarks with the same content share it, deleting one keeps the other
+            let second = bookmark("second", &["fn second() {}"]);
+            let alias = bookmark("alias", &["fn second() {}"]);
+            assert_eq!(alias.id, second.id);
+            store.create(&second).unwrap();
+            store.create(&alias).unwrap();
+            store.delete("second").unwrap();
+            assert_eq!(store.get("alias").unwrap(), Some(alias.clone()));
+            assert!(store.fsck(false).unwrap().is_empty());
+
+            store.copy("alias", "copy").unwrap();
+            store.delete("alias").unwrap();
+            assert_eq!(store.get("copy").unwrap().unwrap().id, second.id);
+            assert!(store.fsck(false).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn renames_and_copies_bookmarks() {
+        let tmp = tempfile::tempdir().unwrap();
+        for mut store in stores(tmp.path()) {
+            let first = bookmark("first", &["fn first() {}"]);
+            let alias = bookmark("alias", &["fn alias() {}"]);
+            store.create(&first).unwrap();
+            store.create(&alias).unwrap();
+
+            // Renamed and copied bookmarks keep everything but their name
+            store.rename("first", "rust/first").unwrap();
+            assert!(!store.exists("first").unwrap());
+            let moved = Bookmark {
+                name: String::from("rust/first"),
+                ..first.clone()
+            };
+            assert_eq!(store.get("rust/first").unwrap(), Some(moved));
+
+            store.copy("alias", "copy").unwrap();
+            let copy = Bookmark {
+                name: String::from("copy"),
+                ..alias.clone()
+            };
+            assert_eq!(store.get("copy").unwrap(), Some(copy));
+            assert_eq!(store.get("alias").unwrap(), Some(alias));
+
+            for (name, new_name, kind) in [
+                ("missing", "other", io::ErrorKind::NotFound),
+                ("alias", "copy", io::ErrorKind::AlreadyExists),
+                ("alias", "alias", io::ErrorKind::AlreadyExists),
+                ("alias", "rust/", io::ErrorKind::InvalidInput),
+            ] {
+                assert_eq!(store.rename(name, new_name).unwrap_err().kind(), kind);
+                assert_eq!(store.copy(name, new_name).unwrap_err().kind(), kind);
+            }
+            assert!(store.fsck(false).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn keeps_history() {
+        let tmp = tempfile::tempdir().unwrap();
+        for mut store in stores(tmp.path()) {
+            let first = bookmark("first", &["fn first() {", "    1", "}"]);
+            store.create(&first).unwrap();
+
+            // Updates keep the previous content as a version, which moves
+            // along with the bookmark and is shared by copies
+            let mut edited = first.clone();
+            edited.set_content(&[String::from("fn first() {}")]);
+            store.update(&edited).unwrap();
+            assert_eq!(store.get("first").unwrap(), Some(edited.clone()));
+            assert_eq!(edited.history, vec![first.id.clone()]);
+
+            store.rename("first", "rust/first").unwrap();
+            store.copy("rust/first", "first copy").unwrap();
+            store.delete("rust/first").unwrap();
+            let copy = store.get("first copy").unwrap().unwrap();
+            assert_eq!(copy.history, edited.history);
+            assert!(store.fsck(false).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn keeps_metadata() {
+        let tmp = tempfile::tempdir().unwrap();
+        for mut store in stores(tmp.path()) {
+            let mut first = bookmark("first", &["fn first() {}"]);
+            first.description = String::from("First function");
+            first.tags = vec![String::from("rust"), String::from("example")];
+            store.create(&first).unwrap();
+            assert_eq!(store.get("first").unwrap(), Some(first.clone()));
+
+            first.notes = String::from("# Notes\n\nReturns `()`");
+            first.urls = vec![String::from("https://example.com/pull/1")];
+            first.touch();
+            store.update(&first).unwrap();
+            assert_eq!(store.get("first").unwrap(), Some(first.clone()));
+
+            store.copy("first", "copy").unwrap();
+            let copy = store.get("copy").unwrap().unwrap();
+            assert_eq!(
+                (&copy.description, &copy.tags, &copy.notes, &copy.urls),
+                (&first.description, &first.tags, &first.notes, &first.urls)
+            );
+        }
+    }
+
+    #[test]
+    fn keeps_origin() {
+        let tmp = tempfile::tempdir().unwrap();
+        for mut store in stores(tmp.path()) {
+            let mut first = bookmark("first", &["fn first() {", "    1", "}"]);
+            first.source = String::from("src/lib.rs");
+            first.origin = vec![4, 6, 7];
+            store.create(&first).unwrap();
+            assert_eq!(store.get("first").unwrap(), Some(first.clone()));
+
+            // New content was not captured from the lines of the source
+            let mut edited = first.clone();
+            edited.set_content(&[String::from("fn first() {}")]);
+            store.update(&edited).unwrap();
+            let saved = store.get("first").unwrap().unwrap();
+            assert_eq!(saved.source, "src/lib.rs");
+            assert!(saved.origin.is_empty());
+        }
+    }
+
+    #[test]
+    fn checks_names() {
+        assert!(super::check_name("first").is_ok());
+        assert!(super::check_name("rust/iterators/windows").is_ok());
+        for name in ["", "/first", "rust/", "rust//first", "rust/ /first"] {
+            let err = super::check_name(name).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn opens_store_by_layout() {
+        let tmp = tempfile::tempdir().unwrap();
+        assert!(super::open_at(tmp.path()).is_err());
+
+        SqliteStore::init(tmp.path()).unwrap();
+        let mut store = super::open_at(tmp.path()).unwrap();
+        store.create(&bookmark("opened", &["x"])).unwrap();
+
+        assert!(SqliteStore::open(tmp.path()).is_some());
+        assert!(FileStore::open(tmp.path()).is_none());
+    }
+}
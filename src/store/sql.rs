@@ -0,0 +1,301 @@
+use sqlite::{Connection, State, Statement};
+use std::collections::BTreeMap;
+use std::io;
+
+use crate::bookmark::Bookmark;
+use crate::utils;
+
+/// Prepares `statement` with `params` bound to its `?` placeholders in order.
+pub(super) fn prepare<'l>(
+    conn: &'l Connection,
+    statement: &str,
+    params: &[&str],
+) -> io::Result<Statement<'l>> {
+    let mut statement = conn.prepare(statement).map_err(io::Error::other)?;
+    for (idx, param) in params.iter().enumerate() {
+        statement.bind(idx + 1, *param).map_err(io::Error::other)?;
+    }
+
+    Ok(statement)
+}
+
+pub(super) fn execute(conn: &Connection, statement: &str, params: &[&str]) -> io::Result<()> {
+    let mut statement = prepare(conn, statement, params)?;
+    while statement.next().map_err(io::Error::other)? == State::Row {}
+    Ok(())
+}
+
+pub(super) fn has_table(conn: &Connection, name: &str) -> io::Result<bool> {
+    let mut statement = prepare(
+        conn,
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name = ?;",
+        &[name],
+    )?;
+    Ok(statement.next().map_err(io::Error::other)? == State::Row)
+}
+
+/// Version of the schema, kept in sqlite's `user_version` pragma.
+pub(super) fn schema_version(conn: &Connection) -> io::Result<i64> {
+    let mut statement = prepare(conn, "PRAGMA user_version;", &[])?;
+    statement.next().map_err(io::Error::other)?;
+    statement.read(0).map_err(io::Error::other)
+}
+
+pub(super) fn set_schema_version(conn: &Connection, version: i64) -> io::Result<()> {
+    let statement = format!("PRAGMA user_version = {};", version);
+    conn.execute(statement).map_err(io::Error::other)
+}
+
+/// Reference counts of every blob, by id.
+pub(super) fn refcounts(conn: &Connection) -> io::Result<BTreeMap<String, i64>> {
+    let mut statement = prepare(conn, "SELECT id, refcount FROM blobs;", &[])?;
+
+    let mut refcounts = BTreeMap::new();
+    while statement.next().map_err(io::Error::other)? == State::Row {
+        let id: String = statement.read(0).map_err(io::Error::other)?;
+        let refcount: i64 = statement.read(1).map_err(io::Error::other)?;
+        refcounts.insert(id, refcount);
+    }
+
+    Ok(refcounts)
+}
+
+/// Adds a reference to the blob `id`, returning `true` if it is its first one
+/// and the content still has to be stored.
+pub(super) fn add_reference(conn: &Connection, id: &str) -> io::Result<bool> {
+    let is_new = !blob_exists(conn, id)?;
+
+    execute(
+        conn,
+        "INSERT OR IGNORE INTO blobs (id, refcount) VALUES (?, 0);",
+        &[id],
+    )?;
+    execute(
+        conn,
+        "UPDATE blobs SET refcount = refcount + 1 WHERE id = ?;",
+        &[id],
+    )?;
+
+    Ok(is_new)
+}
+
+/// Removes a reference to the blob `id`, deleting the blob once nothing
+/// refers to it. Returns `true` if it was deleted.
+pub(super) fn remove_reference(conn: &Connection, id: &str) -> io::Result<bool> {
+    execute(
+        conn,
+        "UPDATE blobs SET refcount = refcount - 1 WHERE id = ?;",
+        &[id],
+    )?;
+    execute(
+        conn,
+        "DELETE FROM blobs WHERE id = ? AND refcount <= 0;",
+        &[id],
+    )?;
+
+    Ok(!blob_exists(conn, id)?)
+}
+
+/// Sets the reference count of the blob `id` to the number of bookmarks and
+/// versions pointing to it, deleting the blob if there are none.
+pub(super) fn recount(conn: &Connection, id: &str) -> io::Result<()> {
+    execute(
+        conn,
+        "INSERT OR IGNORE INTO blobs (id, refcount) VALUES (?, 0);",
+        &[id],
+    )?;
+    execute(
+        conn,
+        "UPDATE blobs SET refcount = (SELECT COUNT(*) FROM bookmarks WHERE id = ?) \
+         + (SELECT COUNT(*) FROM history WHERE id = ?) WHERE id = ?;",
+        &[id, id, id],
+    )?;
+    execute(
+        conn,
+        "DELETE FROM blobs WHERE id = ? AND refcount <= 0;",
+        &[id],
+    )
+}
+
+/// Previous versions of every bookmark, as the ids of their content ordered
+/// from oldest to newest.
+pub(super) fn create_history_table(conn: &Connection) -> io::Result<()> {
+    execute(
+        conn,
+        "CREATE TABLE history (name TEXT NOT NULL, version INTEGER NOT NULL, id TEXT NOT NULL, \
+         PRIMARY KEY (name, version));",
+        &[],
+    )
+}
+
+/// History of every bookmark, by name.
+pub(super) fn history(conn: &Connection) -> io::Result<BTreeMap<String, Vec<String>>> {
+    let mut statement = prepare(
+        conn,
+        "SELECT name, id FROM history ORDER BY name, version;",
+        &[],
+    )?;
+
+    let mut history: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    while statement.next().map_err(io::Error::other)? == State::Row {
+        let name: String = statement.read(0).map_err(io::Error::other)?;
+        let id: String = statement.read(1).map_err(io::Error::other)?;
+        history.entry(name).or_default().push(id);
+    }
+
+    Ok(history)
+}
+
+/// History of the bookmark `name`.
+pub(super) fn history_of(conn: &Connection, name: &str) -> io::Result<Vec<String>> {
+    let mut statement = prepare(
+        conn,
+        "SELECT id FROM history WHERE name = ? ORDER BY version;",
+        &[name],
+    )?;
+
+    let mut history = Vec::new();
+    while statement.next().map_err(io::Error::other)? == State::Row {
+        history.push(statement.read(0).map_err(io::Error::other)?);
+    }
+
+    Ok(history)
+}
+
+/// Lists like tags and urls are kept in a single column, one item per line.
+pub(super) fn join_list(items: &[String]) -> String {
+    items.join("\n")
+}
+
+pub(super) fn split_list(items: &str) -> Vec<String> {
+    items.lines().map(String::from).collect()
+}
+
+/// Metadata columns of `bookmarks`, with the schema version that added them.
+const METADATA_COLUMNS: [(i64, &str); 7] = [
+    (3, "description"),
+    (3, "tags"),
+    (4, "notes"),
+    (4, "urls"),
+    (5, "source"),
+    (5, "origin"),
+    (6, "updated"),
+];
+
+/// Adds the metadata columns missing from a `bookmarks` table created with
+/// schema `version`.
+pub(super) fn add_metadata_columns(conn: &Connection, version: i64) -> io::Result<()> {
+    for (added, column) in METADATA_COLUMNS {
+        if version < added {
+            let statement = format!(
+                "ALTER TABLE bookmarks ADD COLUMN {} TEXT NOT NULL DEFAULT '';",
+                column
+            );
+            execute(conn, &statement, &[])?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Inserts the rows of `bookmark` and takes a reference to its content and
+/// to the content of every version in its history. Returns `true` if the
+/// content is not stored yet.
+pub(super) fn insert_bookmark(conn: &Connection, bookmark: &Bookmark) -> io::Result<bool> {
+    execute(
+        conn,
+        "INSERT INTO bookmarks (name, id, lang, description, tags, notes, urls, source, origin, \
+         updated) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?);",
+        &[
+            &bookmark.name,
+            &bookmark.id,
+            &bookmark.lang.to_extension(),
+            &bookmark.description,
+            &join_list(&bookmark.tags),
+            &bookmark.notes,
+            &join_list(&bookmark.urls),
+            &bookmark.source,
+            &utils::format_ranges(&bookmark.origin),
+            &bookmark.updated.to_string(),
+        ],
+    )?;
+
+    for (version, id) in bookmark.history.iter().enumerate() {
+        execute(
+            conn,
+            "INSERT INTO history (name, version, id) VALUES (?, ?, ?);",
+            &[&bookmark.name, &(version + 1).to_string(), id],
+        )?;
+        add_reference(conn, id)?;
+    }
+
+    add_reference(conn, &bookmark.id)
+}
+
+/// Removes the rows of the bookmark `name` and the references they hold,
+/// returning the ids of the content nothing refers to anymore.
+pub(super) fn remove_bookmark(conn: &Connection, name: &str) -> io::Result<Vec<String>> {
+    let ids = delete_bookmark(conn, name)?;
+    release(conn, &ids)
+}
+
+/// Replaces the rows of the bookmark with the same name as `bookmark`.
+/// References to the new content are taken before the old ones are
+/// released, so content kept across versions is never dropped. Returns
+/// whether the content is not stored yet, and the ids of the content nothing
+/// refers to anymore.
+pub(super) fn replace_bookmark(
+    conn: &Connection,
+    bookmark: &Bookmark,
+) -> io::Result<(bool, Vec<String>)> {
+    let ids = delete_bookmark(conn, &bookmark.name)?;
+    let is_new = insert_bookmark(conn, bookmark)?;
+    Ok((is_new, release(conn, &ids)?))
+}
+
+/// Deletes the rows of the bookmark `name`, returning the ids of the content
+/// they referred to.
+fn delete_bookmark(conn: &Connection, name: &str) -> io::Result<Vec<String>> {
+    let mut statement = prepare(conn, "SELECT id FROM bookmarks WHERE name = ?;", &[name])?;
+    if statement.next().map_err(io::Error::other)? != State::Row {
+        return Err(super::not_found(name));
+    }
+    let mut ids: Vec<String> = vec![statement.read(0).map_err(io::Error::other)?];
+    drop(statement);
+    ids.extend(history_of(conn, name)?);
+
+    execute(conn, "DELETE FROM bookmarks WHERE name = ?;", &[name])?;
+    execute(conn, "DELETE FROM history WHERE name = ?;", &[name])?;
+    Ok(ids)
+}
+
+/// Removes a reference to each of `ids`, returning the ones nothing refers
+/// to anymore.
+fn release(conn: &Connection, ids: &[String]) -> io::Result<Vec<String>> {
+    let mut unreferenced = Vec::new();
+    for id in ids {
+        if remove_reference(conn, id)? {
+            unreferenced.push(id.clone());
+        }
+    }
+
+    Ok(unreferenced)
+}
+
+pub(super) fn rename_bookmark(conn: &Connection, name: &str, new_name: &str) -> io::Result<()> {
+    execute(
+        conn,
+        "UPDATE bookmarks SET name = ? WHERE name = ?;",
+        &[new_name, name],
+    )?;
+    execute(
+        conn,
+        "UPDATE history SET name = ? WHERE name = ?;",
+        &[new_name, name],
+    )
+}
+
+pub(super) fn blob_exists(conn: &Connection, id: &str) -> io::Result<bool> {
+    let mut statement = prepare(conn, "SELECT refcount FROM blobs WHERE id = ?;", &[id])?;
+    Ok(statement.next().map_err(io::Error::other)? == State::Row)
+}
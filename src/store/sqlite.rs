@@ -0,0 +1,426 @@
+use sqlite::{self, State};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::{sql, Problem, SnippetStore};
+use crate::bookmark::Bookmark;
+use crate::language::Language;
+use crate::utils;
+
+const DATABASE_FILE_NAME: &str = "capture.db";
+
+/// Version 0 kept the content in the `bookmarks` table itself. Version 1
+/// moves it to reference counted `blobs` shared by bookmarks. Version 2 ids
+/// are computed with `utils::content_hash`. Version 3 adds the description
+/// and tags of the bookmarks, and the `history` of their content. Version 4
+/// adds their notes and related urls. Version 5 adds the file and lines the
+/// content was captured from. Version 6 adds when the bookmarks were last
+/// updated.
+const SCHEMA_VERSION: i64 = 6;
+
+/// Store kept in a single sqlite file, with the content of each bookmark
+/// in a BLOB column shared by all bookmarks with the same content.
+pub struct SqliteStore {
+    path: PathBuf,
+}
+
+impl SqliteStore {
+    /// Opens the store at `root`, if one has been initialized there.
+    pub fn open<P: AsRef<Path>>(root: P) -> Option<Self> {
+        let path = root.as_ref().join(DATABASE_FILE_NAME);
+        if !path.exists() {
+            return None;
+        }
+
+        Some(SqliteStore { path })
+    }
+
+    pub fn init<P: AsRef<Path>>(root: P) -> io::Result<Self> {
+        fs::create_dir_all(&root)?;
+        let store = SqliteStore {
+            path: root.as_ref().join(DATABASE_FILE_NAME),
+        };
+
+        let conn = store.get_connection()?;
+        Self::create_tables(&conn)?;
+        sql::create_history_table(&conn)?;
+        sql::set_schema_version(&conn, SCHEMA_VERSION)?;
+
+        Ok(store)
+    }
+
+    fn create_tables(conn: &sqlite::Connection) -> io::Result<()> {
+        sql::execute(
+            conn,
+            "CREATE TABLE bookmarks (name TEXT PRIMARY KEY, id TEXT NOT NULL, lang TEXT, \
+             description TEXT NOT NULL DEFAULT '', tags TEXT NOT NULL DEFAULT '', \
+             notes TEXT NOT NULL DEFAULT '', urls TEXT NOT NULL DEFAULT '', \
+             source TEXT NOT NULL DEFAULT '', origin TEXT NOT NULL DEFAULT '', \
+             updated TEXT NOT NULL DEFAULT '');",
+            &[],
+        )?;
+        sql::execute(
+            conn,
+            "CREATE TABLE blobs (id TEXT PRIMARY KEY, refcount INTEGER NOT NULL, content BLOB);",
+            &[],
+        )
+    }
+
+    /// Opens the database, migrating it to the current schema if needed.
+    fn get_connection(&self) -> io::Result<sqlite::Connection> {
+        let conn = match sqlite::open(&self.path) {
+            Ok(conn) => conn,
+            Err(e) => return Err(io::Error::other(e)),
+        };
+
+        if sql::has_table(&conn, "bookmarks")? && sql::schema_version(&conn)? < SCHEMA_VERSION {
+            super::transaction(&conn, || Self::migrate(&conn))?;
+        }
+
+        Ok(conn)
+    }
+
+    fn migrate(conn: &sqlite::Connection) -> io::Result<()> {
+        let version = sql::schema_version(conn)?;
+
+        // References are counted over the history, so it has to exist first
+        if version < 3 {
+            sql::create_history_table(conn)?;
+        }
+
+        // Version 0 is moved to the current tables, later ones get the
+        // metadata columns added since
+        if version < 1 {
+            Self::migrate_v0(conn)?;
+        } else {
+            sql::add_metadata_columns(conn, version)?;
+        }
+
+        if version < 2 {
+            Self::migrate_v1(conn)?;
+        }
+
+        sql::set_schema_version(conn, SCHEMA_VERSION)
+    }
+
+    /// Moves the bookmarks and their content to the current tables.
+    fn migrate_v0(conn: &sqlite::Connection) -> io::Result<()> {
+        sql::execute(conn, "ALTER TABLE bookmarks RENAME TO bookmarks_v0;", &[])?;
+        Self::create_tables(conn)?;
+
+        for statement in [
+            "INSERT INTO bookmarks (name, id, lang) SELECT name, id, lang FROM bookmarks_v0;",
+            "INSERT INTO blobs (id, refcount, content) \
+             SELECT id, COUNT(*), content FROM bookmarks_v0 GROUP BY id;",
+            "DROP TABLE bookmarks_v0;",
+        ] {
+            sql::execute(conn, statement, &[])?;
+        }
+
+        Ok(())
+    }
+
+    /// Rehashes the content with the current content hash.
+    fn migrate_v1(conn: &sqlite::Connection) -> io::Result<()> {
+        for (id, actual) in Self::hashes(conn)? {
+            if actual != id {
+                Self::rehash(conn, &id, &actual)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Id of every blob along with the hash of its content.
+    fn hashes(conn: &sqlite::Connection) -> io::Result<Vec<(String, String)>> {
+        let mut statement = sql::prepare(conn, "SELECT id, content FROM blobs;", &[])?;
+
+        let mut hashes = Vec::new();
+        while statement.next().map_err(io::Error::other)? == State::Row {
+            let id: String = statement.read(0).map_err(io::Error::other)?;
+            let content: Option<Vec<u8>> = statement.read(1).map_err(io::Error::other)?;
+
+            let lines: Vec<String> = String::from_utf8_lossy(&content.unwrap_or_default())
+                .lines()
+                .map(String::from)
+                .collect();
+            hashes.push((id, utils::content_hash(&lines)));
+        }
+
+        Ok(hashes)
+    }
+
+    /// Moves the blob `id` and the bookmarks and versions pointing to it to
+    /// `actual`.
+    fn rehash(conn: &sqlite::Connection, id: &str, actual: &str) -> io::Result<()> {
+        for statement in [
+            "UPDATE bookmarks SET id = ? WHERE id = ?;",
+            "UPDATE history SET id = ? WHERE id = ?;",
+            // Keep the existing blob if the content is already stored
+            "UPDATE OR IGNORE blobs SET id = ? WHERE id = ?;",
+        ] {
+            sql::execute(conn, statement, &[actual, id])?;
+        }
+        sql::recount(conn, id)?;
+        sql::recount(conn, actual)
+    }
+
+    /// Runs a `SELECT name, id, lang, description, tags, notes, urls, source,
+    /// origin, updated, content` query and builds the bookmarks from its rows.
+    fn query(&self, statement: &str, params: &[&str]) -> io::Result<Vec<Bookmark>> {
+        let conn = self.get_connection()?;
+        let mut history = sql::history(&conn)?;
+        let mut statement = sql::prepare(&conn, statement, params)?;
+
+        let mut bookmarks = Vec::new();
+        while statement.next().map_err(io::Error::other)? == State::Row {
+            let name: String = statement.read(0).map_err(io::Error::other)?;
+            let id: String = statement.read(1).map_err(io::Error::other)?;
+            let lang: String = statement.read(2).map_err(io::Error::other)?;
+            let description: String = statement.read(3).map_err(io::Error::other)?;
+            let tags: String = statement.read(4).map_err(io::Error::other)?;
+            let notes: String = statement.read(5).map_err(io::Error::other)?;
+            let urls: String = statement.read(6).map_err(io::Error::other)?;
+            let source: String = statement.read(7).map_err(io::Error::other)?;
+            let origin: String = statement.read(8).map_err(io::Error::other)?;
+            let updated: String = statement.read(9).map_err(io::Error::other)?;
+            let content: Option<Vec<u8>> = statement.read(10).map_err(io::Error::other)?;
+
+            let content = match content {
+                Some(content) => content,
+                None => return Err(super::missing_content(&name)),
+            };
+
+            bookmarks.push(Bookmark {
+                id,
+                lang: Language::from_extension(&lang),
+                content: String::from_utf8_lossy(&content)
+                    .lines()
+                    .map(String::from)
+                    .collect(),
+                description,
+                tags: sql::split_list(&tags),
+                notes,
+                urls: sql::split_list(&urls),
+                history: history.remove(&name).unwrap_or_default(),
+                source,
+                origin: utils::parse_ranges(&origin),
+                updated: updated.parse().unwrap_or(0),
+                name,
+            });
+        }
+
+        Ok(bookmarks)
+    }
+
+    /// Inserts `bookmark`, replacing the one with the same name if `replace`
+    /// is set, and stores its content unless it is already stored.
+    fn save(&self, bookmark: &Bookmark, replace: bool) -> io::Result<()> {
+        let content = utils::join_lines(&bookmark.content);
+
+        let conn = self.get_connection()?;
+        super::transaction(&conn, || {
+            let is_new = match replace {
+                true => sql::replace_bookmark(&conn, bookmark)?.0,
+                false => sql::insert_bookmark(&conn, bookmark)?,
+            };
+
+            // Content already stored for another bookmark is shared
+            if !is_new {
+                return Ok(());
+            }
+
+            let mut statement =
+                sql::prepare(&conn, "UPDATE blobs SET content = ? WHERE id = ?;", &[])?;
+            statement
+                .bind(1, content.as_bytes())
+                .and_then(|_| statement.bind(2, bookmark.id.as_str()))
+                .and_then(|_| statement.next())
+                .map_err(io::Error::other)?;
+            Ok(())
+        })
+    }
+}
+
+impl SnippetStore for SqliteStore {
+    fn create(&mut self, bookmark: &Bookmark) -> io::Result<()> {
+        super::check_name(&bookmark.name)?;
+        if self.exists(&bookmark.name)? {
+            return Err(super::already_exists(&bookmark.name));
+        }
+
+        self.save(bookmark, false)
+    }
+
+    fn update(&mut self, bookmark: &Bookmark) -> io::Result<()> {
+        self.save(bookmark, true)
+    }
+
+    fn delete(&mut self, name: &str) -> io::Result<()> {
+        let conn = self.get_connection()?;
+        super::transaction(&conn, || sql::remove_bookmark(&conn, name).map(|_| ()))
+    }
+
+    fn rename(&mut self, name: &str, new_name: &str) -> io::Result<()> {
+        super::check_name(new_name)?;
+        if !self.exists(name)? {
+            return Err(super::not_found(name));
+        }
+        if self.exists(new_name)? {
+            return Err(super::already_exists(new_name));
+        }
+
+        let conn = self.get_connection()?;
+        super::transaction(&conn, || sql::rename_bookmark(&conn, name, new_name))
+    }
+
+    fn get(&self, name: &str) -> io::Result<Option<Bookmark>> {
+        let statement =
+            "SELECT name, bookmarks.id, lang, description, tags, notes, urls, source, origin, updated, \
+                         content \
+                         FROM bookmarks LEFT JOIN blobs ON blobs.id = bookmarks.id WHERE name = ?;";
+        Ok(self.query(statement, &[name])?.pop())
+    }
+
+    fn all(&self) -> io::Result<Vec<Bookmark>> {
+        let statement =
+            "SELECT name, bookmarks.id, lang, description, tags, notes, urls, source, origin, updated, \
+                         content \
+                         FROM bookmarks LEFT JOIN blobs ON blobs.id = bookmarks.id;";
+        self.query(statement, &[])
+    }
+
+    fn fsck(&mut self, repair: bool) -> io::Result<Vec<Problem>> {
+        let conn = self.get_connection()?;
+        let refcounts = sql::refcounts(&conn)?;
+
+        // Bookmarks pointing to each content, and whether it is their current
+        // content or a previous version
+        let mut references: BTreeMap<String, Vec<(String, bool)>> = BTreeMap::new();
+        let mut statement = sql::prepare(&conn, "SELECT name, id FROM bookmarks;", &[])?;
+        while statement.next().map_err(io::Error::other)? == State::Row {
+            let name: String = statement.read(0).map_err(io::Error::other)?;
+            let id: String = statement.read(1).map_err(io::Error::other)?;
+            references.entry(id).or_default().push((name, true));
+        }
+        drop(statement);
+        for (name, ids) in sql::history(&conn)? {
+            for id in ids {
+                references
+                    .entry(id)
+                    .or_default()
+                    .push((name.clone(), false));
+            }
+        }
+
+        let mut problems: Vec<Problem> = refcounts
+            .keys()
+            .filter(|id| !references.contains_key(*id))
+            .map(|id| Problem::OrphanContent(id.clone()))
+            .collect();
+
+        for (id, holders) in &references {
+            match refcounts.get(id) {
+                Some(stored) if *stored != holders.len() as i64 => {
+                    problems.push(Problem::RefcountMismatch {
+                        id: id.clone(),
+                        stored: *stored,
+                        actual: holders.len() as i64,
+                    })
+                }
+                Some(_) => (),
+                None => problems.extend(
+                    holders
+                        .iter()
+                        .map(|(name, current)| super::missing(name, id, *current)),
+                ),
+            }
+        }
+
+        for (id, actual) in Self::hashes(&conn)? {
+            let holders = match references.get(&id) {
+                Some(holders) => holders,
+                None => continue,
+            };
+
+            if actual != id {
+                problems.push(Problem::HashMismatch {
+                    name: holders[0].0.clone(),
+                    id,
+                    actual,
+                });
+            }
+        }
+
+        if repair {
+            super::transaction(&conn, || {
+                for problem in &problems {
+                    match problem {
+                        Problem::OrphanContent(id) => {
+                            sql::execute(&conn, "DELETE FROM blobs WHERE id = ?;", &[id])?
+                        }
+                        Problem::MissingContent(name) => {
+                            sql::remove_bookmark(&conn, name)?;
+                        }
+                        Problem::MissingVersion { name, id } => {
+                            sql::execute(
+                                &conn,
+                                "DELETE FROM history WHERE name = ? AND id = ?;",
+                                &[name, id],
+                            )?;
+                            sql::recount(&conn, id)?
+                        }
+                        Problem::RefcountMismatch { id, .. } => sql::recount(&conn, id)?,
+                        Problem::HashMismatch { id, actual, .. } => {
+                            Self::rehash(&conn, id, actual)?
+                        }
+                    }
+                }
+                Ok(())
+            })?;
+        }
+
+        Ok(problems)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SqliteStore;
+    use crate::language::Language;
+    use crate::store::tests::bookmark;
+    use crate::store::SnippetStore;
+
+    #[test]
+    fn migrates_schema_v0() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut first = bookmark("first", &["def first():", "    pass"]);
+        first.lang = Language::Python;
+        // Bookmarks saved before version 6 have no update date
+        first.updated = 0;
+
+        let conn = sqlite::open(tmp.path().join("capture.db")).unwrap();
+        conn.execute(
+            "CREATE TABLE bookmarks (name TEXT PRIMARY KEY, id TEXT, lang TEXT, content BLOB);",
+        )
+        .unwrap();
+        // Ids used to be computed with an older hash
+        for name in ["first", "alias"] {
+            conn.execute(format!(
+                "INSERT INTO bookmarks VALUES ('{}', 'old', 'py', 'def first():\n    pass\n');",
+                name
+            ))
+            .unwrap();
+        }
+
+        let mut store = SqliteStore::open(tmp.path()).unwrap();
+        assert_eq!(store.get("first").unwrap(), Some(first.clone()));
+        assert!(store.fsck(false).unwrap().is_empty());
+
+        store.delete("alias").unwrap();
+        assert_eq!(store.get("first").unwrap().unwrap().content, first.content);
+        assert!(store.fsck(false).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,481 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io;
+use std::str::FromStr;
+
+use crate::bookmark::Bookmark;
+use crate::highlight::Highlighter;
+
+lazy_static! {
+    /// `${1:name}` tabstops, as used by editor snippets, and `{{name}}`
+    /// variables, as used by most template engines.
+    static ref PLACEHOLDER: Regex = Regex::new(
+        r"\$\{(\d+):([A-Za-z_][A-Za-z0-9_]*)\}|\{\{\s*([A-Za-z_][A-Za-z0-9_]*)\s*\}\}"
+    )
+    .unwrap();
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Text(String),
+    Variable {
+        name: String,
+        tabstop: Option<usize>,
+    },
+}
+
+/// Content of a bookmark split into text and the placeholders to fill in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    lines: Vec<Vec<Segment>>,
+}
+
+impl Template {
+    pub fn parse(content: &[String]) -> Self {
+        let lines = content
+            .iter()
+            .map(|line| {
+                let mut segments = Vec::new();
+                let mut last = 0;
+                for captures in PLACEHOLDER.captures_iter(line) {
+                    let whole = captures.get(0).unwrap();
+                    if whole.start() > last {
+                        segments.push(Segment::Text(line[last..whole.start()].to_string()));
+                    }
+                    last = whole.end();
+
+                    segments.push(match (captures.get(1), captures.get(2)) {
+                        (Some(tabstop), Some(name)) => Segment::Variable {
+                            name: name.as_str().to_string(),
+                            tabstop: tabstop.as_str().parse().ok(),
+                        },
+                        _ => Segment::Variable {
+                            name: captures[3].to_string(),
+                            tabstop: None,
+                        },
+                    });
+                }
+                if last < line.len() || segments.is_empty() {
+                    segments.push(Segment::Text(line[last..].to_string()));
+                }
+                segments
+            })
+            .collect();
+
+        Template { lines }
+    }
+
+    /// Checks that explicit tabstops can be exported as they are: none is 0,
+    /// which editors take as the final cursor position, no two variables
+    /// share one, and no variable has two.
+    pub fn check_tabstops(&self) -> io::Result<()> {
+        let invalid = |err_msg: String| io::Error::new(io::ErrorKind::InvalidInput, err_msg);
+        let mut tabstops: BTreeMap<usize, &str> = BTreeMap::new();
+
+        for segment in self.lines.iter().flatten() {
+            let Segment::Variable {
+                name,
+                tabstop: Some(tabstop),
+            } = segment
+            else {
+                continue;
+            };
+
+            if *tabstop == 0 {
+                return Err(invalid(format!(
+                    "Tabstop 0 of '{}' is the final cursor position, number variables from 1",
+                    name
+                )));
+            }
+            if let Some((other, _)) = tabstops
+                .iter()
+                .find(|(other, known)| *known == name && *other != tabstop)
+            {
+                return Err(invalid(format!(
+                    "Variable '{}' has tabstops {} and {}",
+                    name, other, tabstop
+                )));
+            }
+            match tabstops.insert(*tabstop, name) {
+                Some(known) if known != name => {
+                    return Err(invalid(format!(
+                        "Variables '{}' and '{}' share tabstop {}",
+                        known, name, tabstop
+                    )));
+                }
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Variables of the template with the tabstop each one is bound to.
+    /// Variables with an explicit tabstop keep it, the rest are numbered
+    /// after them in order of appearance.
+    fn tabstops(&self) -> Vec<(String, usize)> {
+        let mut tabstops: Vec<(String, usize)> = Vec::new();
+        let mut unnumbered: Vec<String> = Vec::new();
+
+        for segment in self.lines.iter().flatten() {
+            if let Segment::Variable { name, tabstop } = segment {
+                match tabstop {
+                    Some(tabstop) if !tabstops.iter().any(|(known, _)| known == name) => {
+                        tabstops.push((name.clone(), *tabstop));
+                    }
+                    _ if !unnumbered.contains(name) => unnumbered.push(name.clone()),
+                    _ => (),
+                }
+            }
+        }
+
+        let mut next = tabstops
+            .iter()
+            .map(|(_, tabstop)| *tabstop)
+            .max()
+            .unwrap_or(0)
+            + 1;
+        for name in unnumbered {
+            if !tabstops.iter().any(|(known, _)| *known == name) {
+                tabstops.push((name, next));
+                next += 1;
+            }
+        }
+
+        tabstops.sort_by_key(|(_, tabstop)| *tabstop);
+        tabstops
+    }
+
+    /// Names of the variables, in the order they should be filled in.
+    pub fn variables(&self) -> Vec<String> {
+        self.tabstops().into_iter().map(|(name, _)| name).collect()
+    }
+
+    /// Variables that have no value in `values`.
+    pub fn missing(&self, values: &BTreeMap<String, String>) -> Vec<String> {
+        self.variables()
+            .into_iter()
+            .filter(|name| !values.contains_key(name))
+            .collect()
+    }
+
+    /// Names in `values` that are not variables of the template.
+    pub fn unknown<'a>(&self, values: &'a BTreeMap<String, String>) -> Vec<&'a str> {
+        let variables = self.variables();
+        values
+            .keys()
+            .filter(|name| !variables.contains(name))
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Replaces every placeholder with its value. Every variable needs a
+    /// value, and every value has to belong to a variable.
+    pub fn render(&self, values: &BTreeMap<String, String>) -> io::Result<Vec<String>> {
+        self.check_tabstops()?;
+        let unknown = self.unknown(values);
+        if !unknown.is_empty() {
+            let err_msg = format!("Unknown variable(s): {}", quoted(&unknown));
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, err_msg));
+        }
+
+        let missing = self.missing(values);
+        if !missing.is_empty() {
+            let err_msg = format!(
+                "Missing value for variable(s): {}, set them with --set name=value",
+                quoted(&missing)
+            );
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, err_msg));
+        }
+
+        Ok(self
+            .lines
+            .iter()
+            .map(|line| {
+                line.iter()
+                    .map(|segment| match segment {
+                        Segment::Text(text) => text.as_str(),
+                        Segment::Variable { name, .. } => values[name].as_str(),
+                    })
+                    .collect()
+            })
+            .collect())
+    }
+
+    /// Lines of the template in the snippet syntax shared by most editors:
+    /// the first use of a variable becomes `${1:name}` and later ones mirror
+    /// it with `${1}`. `escape` is applied to the text around them.
+    fn snippet_body(&self, escape: fn(&str) -> String) -> Vec<String> {
+        let tabstops: BTreeMap<String, usize> = self.tabstops().into_iter().collect();
+        let mut seen = Vec::new();
+
+        self.lines
+            .iter()
+            .map(|line| {
+                line.iter()
+                    .map(|segment| match segment {
+                        Segment::Text(text) => escape(text),
+                        Segment::Variable { name, .. } if seen.contains(name) => {
+                            format!("${{{}}}", tabstops[name])
+                        }
+                        Segment::Variable { name, .. } => {
+                            seen.push(name.clone());
+                            format!("${{{}:{}}}", tabstops[name], name)
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+fn quoted<S: AsRef<str>>(names: &[S]) -> String {
+    names
+        .iter()
+        .map(|name| format!("'{}'", name.as_ref()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Parses a `name=value` assignment given on the command line.
+pub fn parse_assignment(assignment: &str) -> Result<(String, String), String> {
+    match assignment.split_once('=') {
+        Some((name, value)) if !name.trim().is_empty() => {
+            Ok((name.trim().to_string(), value.to_string()))
+        }
+        _ => Err(format!("Expected name=value, got '{}'", assignment)),
+    }
+}
+
+/// Editor snippet formats a bookmark can be exported to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SnippetFormat {
+    /// JSON snippets of Visual Studio Code
+    Vscode,
+    /// `.sublime-snippet` files of Sublime Text
+    Sublime,
+    /// UltiSnips snippets for Vim
+    Ultisnips,
+}
+
+impl FromStr for SnippetFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "vscode" => Ok(SnippetFormat::Vscode),
+            "sublime" => Ok(SnippetFormat::Sublime),
+            "ultisnips" => Ok(SnippetFormat::Ultisnips),
+            _ => Err(format!(
+                "Unknown snippet format '{}', expected vscode, sublime or ultisnips",
+                format
+            )),
+        }
+    }
+}
+
+/// Converts `bookmark` to an editor snippet triggered by `prefix`, scoped
+/// for editors that need it to a syntax of `highlighter`.
+pub fn export(
+    bookmark: &Bookmark,
+    format: SnippetFormat,
+    prefix: &str,
+    highlighter: &Highlighter,
+) -> io::Result<String> {
+    let template = Template::parse(&bookmark.content);
+    template.check_tabstops()?;
+
+    match format {
+        SnippetFormat::Vscode => {
+            #[derive(Serialize)]
+            struct Snippet<'a> {
+                prefix: &'a str,
+                body: Vec<String>,
+                #[serde(skip_serializing_if = "str::is_empty")]
+                description: &'a str,
+            }
+
+            let snippet = Snippet {
+                prefix,
+                body: template.snippet_body(escape_snippet),
+                description: &bookmark.description,
+            };
+            let snippets = BTreeMap::from([(bookmark.name.as_str(), snippet)]);
+            serde_json::to_string_pretty(&snippets).map_err(io::Error::other)
+        }
+        SnippetFormat::Sublime => {
+            // `]]>` would end the CDATA section early, so it is split in two
+            let body = template.snippet_body(escape_snippet).join("\n");
+            let mut snippet = format!(
+                "<snippet>\n    <content><![CDATA[\n{}\n]]></content>\n    <tabTrigger>{}</tabTrigger>\n",
+                body.replace("]]>", "]]]]><![CDATA[>"),
+                escape_xml(prefix)
+            );
+            if let Some(scope) = highlighter.scope(&bookmark.lang) {
+                snippet.push_str(&format!("    <scope>{}</scope>\n", scope));
+            }
+            if !bookmark.description.is_empty() {
+                let description = escape_xml(&bookmark.description);
+                snippet.push_str(&format!("    <description>{}</description>\n", description));
+            }
+            snippet.push_str("</snippet>\n");
+            Ok(snippet)
+        }
+        SnippetFormat::Ultisnips => {
+            let body = template.snippet_body(escape_ultisnips).join("\n");
+            Ok(format!(
+                "snippet {} \"{}\"\n{}\nendsnippet\n",
+                prefix,
+                bookmark.description.replace('"', "'"),
+                body
+            ))
+        }
+    }
+}
+
+fn escape_snippet(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('$', "\\$")
+}
+
+fn escape_ultisnips(text: &str) -> String {
+    escape_snippet(text).replace('`', "\\`")
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{export, parse_assignment, SnippetFormat, Template};
+    use crate::bookmark::Bookmark;
+    use crate::color::Colors;
+    use crate::highlight::{Highlighter, DEFAULT_THEME};
+    use crate::language::Language;
+    use std::collections::BTreeMap;
+    use std::io;
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    fn values(values: &[(&str, &str)]) -> BTreeMap<String, String> {
+        values
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn template() -> Template {
+        Template::parse(&lines(&[
+            "struct {{struct_name}} {",
+            "    ${1:field}: u32,",
+            "}",
+            "impl {{ struct_name }} {}",
+        ]))
+    }
+
+    #[test]
+    fn finds_variables() {
+        assert_eq!(template().variables(), vec!["field", "struct_name"]);
+
+        // Template literals and plain braces are left alone
+        let plain = Template::parse(&lines(&["`${name}`", "{ {x} }", "${1:}"]));
+        assert!(plain.variables().is_empty());
+    }
+
+    #[test]
+    fn renders_variables() {
+        let rendered = template()
+            .render(&values(&[("struct_name", "Foo"), ("field", "count")]))
+            .unwrap();
+        assert_eq!(
+            rendered,
+            lines(&["struct Foo {", "    count: u32,", "}", "impl Foo {}"])
+        );
+    }
+
+    #[test]
+    fn validates_variables() {
+        let err = template()
+            .render(&values(&[("struct_name", "Foo")]))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("'field'"));
+
+        let all = values(&[("struct_name", "Foo"), ("field", "x"), ("other", "y")]);
+        assert_eq!(template().unknown(&all), vec!["other"]);
+        assert!(template().render(&all).is_err());
+    }
+
+    #[test]
+    fn rejects_conflicting_tabstops() {
+        let shared = Template::parse(&lines(&["${1:a} ${1:b}"]));
+        let err = shared
+            .render(&values(&[("a", "x"), ("b", "y")]))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("share tabstop 1"));
+
+        let twice = Template::parse(&lines(&["${1:a}", "${2:a}"]));
+        let err = twice.check_tabstops().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        // Mirrors of a variable repeat its tabstop
+        let mirrored = Template::parse(&lines(&["${1:a} ${1:a} {{a}}"]));
+        assert!(mirrored.check_tabstops().is_ok());
+    }
+
+    #[test]
+    fn rejects_tabstop_zero() {
+        let bookmark = Bookmark::new("zero", &lines(&["${0:a}"]), Language::Rust);
+        let highlighter = Highlighter::new(None, DEFAULT_THEME, Colors::None).unwrap();
+        let err = export(&bookmark, SnippetFormat::Vscode, "z", &highlighter).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("final cursor position"));
+    }
+
+    #[test]
+    fn parses_assignments() {
+        assert_eq!(
+            parse_assignment("name=a=b"),
+            Ok((String::from("name"), String::from("a=b")))
+        );
+        assert!(parse_assignment("name").is_err());
+        assert!(parse_assignment("=value").is_err());
+    }
+
+    #[test]
+    fn exports_editor_snippets() {
+        let mut bookmark = Bookmark::new(
+            "rust/struct",
+            &lines(&[
+                "struct {{name}} { cost: ${2:cost} }",
+                "// {{name}} costs $5",
+            ]),
+            Language::Rust,
+        );
+        bookmark.description = String::from("Struct <template>");
+
+        let highlighter = Highlighter::new(None, DEFAULT_THEME, Colors::None).unwrap();
+        let vscode = export(&bookmark, SnippetFormat::Vscode, "st", &highlighter).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&vscode).unwrap();
+        assert_eq!(json["rust/struct"]["prefix"], "st");
+        assert_eq!(
+            json["rust/struct"]["body"],
+            serde_json::json!(["struct ${3:name} { cost: ${2:cost} }", "// ${3} costs \\$5"])
+        );
+
+        let sublime = export(&bookmark, SnippetFormat::Sublime, "st", &highlighter).unwrap();
+        assert!(sublime.contains("<scope>source.rust</scope>"));
+        assert!(sublime.contains("<description>Struct &lt;template&gt;</description>"));
+
+        let ultisnips = export(&bookmark, SnippetFormat::Ultisnips, "st", &highlighter).unwrap();
+        assert!(ultisnips.starts_with("snippet st \"Struct <template>\"\nstruct ${3:name}"));
+        assert!(ultisnips.ends_with("\nendsnippet\n"));
+    }
+}
@@ -2,6 +2,7 @@ use sha2::{Digest, Sha256};
 use std::fs::File;
 use std::io::{self, BufRead};
 use std::path;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub fn read_lines<P>(path: P) -> io::Result<io::Lines<io::BufReader<File>>>
 where
@@ -14,27 +15,162 @@ where
     Ok(lines)
 }
 
-pub fn merkle_tree_hash(lines: &Vec<String>) -> String {
-    if lines.is_empty() {
-        return String::new();
+/// Version of `content_hash`. It is part of the hashed data, and stores
+/// record it so that content saved with an older version can be rehashed.
+pub const CONTENT_HASH_VERSION: u32 = 1;
+
+/// Id of a snippet's content: the hex encoded SHA-256 of the hash version,
+/// the number of lines and every line prefixed by its length. The prefixes
+/// make the encoding unambiguous, so `["a b"]` and `["a", "b"]` get
+/// different ids.
+pub fn content_hash(lines: &[String]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(CONTENT_HASH_VERSION.to_be_bytes());
+    hasher.update((lines.len() as u64).to_be_bytes());
+
+    for line in lines {
+        hasher.update((line.len() as u64).to_be_bytes());
+        hasher.update(line.as_bytes());
     }
-    let mut tmp = lines.clone();
 
-    while tmp.len() > 1 {
-        let first = &tmp[tmp.len() - 1];
-        let second = &tmp[tmp.len() - 2];
+    format!("{:x}", hasher.finalize())
+}
 
-        let combined = format!("{} {}", first, second);
+/// Joins the lines back into the text they were read from, with each line
+/// terminated by a newline.
+pub fn join_lines(lines: &[String]) -> String {
+    lines.iter().map(|line| format!("{}\n", line)).collect()
+}
 
-        let mut hasher = Sha256::new();
-        hasher.update(&combined);
-        let hash = format!("{:x}", hasher.finalize());
+/// Formats line numbers as comma separated ranges, like `3-5,9`.
+pub fn format_ranges(lines: &[usize]) -> String {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for line in lines {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == *line => *end = *line,
+            _ => ranges.push((*line, *line)),
+        }
+    }
+
+    ranges
+        .iter()
+        .map(|(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{}-{}", start, end)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
 
-        tmp.pop().unwrap();
-        tmp.pop().unwrap();
+/// Parses line numbers formatted with `format_ranges`. Anything else is
+/// ignored, as if the line numbers were unknown.
+pub fn parse_ranges(ranges: &str) -> Vec<usize> {
+    let parse = || -> Option<Vec<usize>> {
+        let mut lines = Vec::new();
+        for range in ranges.split(',').filter(|range| !range.is_empty()) {
+            let (start, end) = range.split_once('-').unwrap_or((range, range));
+            lines.extend(start.trim().parse::<usize>().ok()?..=end.trim().parse().ok()?);
+        }
+        Some(lines)
+    };
 
-        tmp.push(hash);
+    parse().unwrap_or_default()
+}
+
+/// Seconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// Formats seconds since the Unix epoch as a UTC date, like `2024-03-09`.
+pub fn format_date(seconds: u64) -> String {
+    // Days to civil date, from Howard Hinnant's `civil_from_days`
+    let days = (seconds / 86_400) as i64 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{content_hash, format_date, format_ranges, parse_ranges};
+    use proptest::prelude::*;
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn formats_line_ranges() {
+        let lines = [3, 4, 5, 9, 11, 12];
+        assert_eq!(format_ranges(&lines), "3-5,9,11-12");
+        assert_eq!(parse_ranges("3-5,9,11-12"), lines);
+        assert_eq!(format_ranges(&[]), "");
+        assert!(parse_ranges("").is_empty());
+        assert!(parse_ranges("3-x").is_empty());
+    }
+
+    #[test]
+    fn formats_dates() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(951_782_400), "2000-02-29");
+        assert_eq!(format_date(1_710_000_000), "2024-03-09");
     }
 
-    tmp[0].clone()
+    #[test]
+    fn hashes_known_collisions_apart() {
+        let pairs = [
+            (lines(&["a b"]), lines(&["a", "b"])),
+            (lines(&["ab"]), lines(&["a", "b"])),
+            (lines(&[]), lines(&[""])),
+            (lines(&[""]), lines(&["", ""])),
+            (lines(&["b", "a"]), lines(&["a", "b"])),
+        ];
+
+        for (first, second) in pairs {
+            assert_ne!(content_hash(&first), content_hash(&second));
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn is_a_file_name_safe_digest(content in prop::collection::vec(".*", 0..8)) {
+            let id = content_hash(&content);
+            prop_assert_eq!(id.len(), 64);
+            prop_assert!(id.chars().all(|c| c.is_ascii_hexdigit()));
+            prop_assert_eq!(id, content_hash(&content));
+        }
+
+        #[test]
+        fn distinct_content_has_distinct_ids(
+            first in prop::collection::vec(".*", 0..8),
+            second in prop::collection::vec(".*", 0..8),
+        ) {
+            prop_assume!(first != second);
+            prop_assert_ne!(content_hash(&first), content_hash(&second));
+        }
+
+        #[test]
+        fn splitting_a_line_changes_the_id(head in ".*", tail in ".*") {
+            let joined = vec![format!("{}{}", head, tail)];
+            prop_assert_ne!(content_hash(&joined), content_hash(&[head, tail]));
+        }
+    }
 }
@@ -0,0 +1,307 @@
+use std::str::FromStr;
+
+use crate::bookmark::Bookmark;
+use crate::utils;
+
+/// How the lines in the gutter are numbered.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineNumbers {
+    /// From 1, as lines of the bookmark
+    Relative,
+    /// As lines of the file the bookmark was captured from, when known
+    Origin,
+}
+
+/// How a bookmark is shown besides its highlighted content.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct View {
+    /// Gutter with the number of each line, if any
+    pub line_numbers: Option<LineNumbers>,
+    /// Box with the name, language, tags and source instead of the
+    /// `Bookmark:` line
+    pub header: bool,
+}
+
+/// Prefixes each of `lines`, the content of `bookmark` as it is printed,
+/// with its number. With origin numbers, lines left out of the bookmark
+/// when it was captured, like stripped comments, are marked between the
+/// lines around them.
+pub fn gutter(bookmark: &Bookmark, lines: &[String], numbers: LineNumbers) -> Vec<String> {
+    let origin = match numbers {
+        LineNumbers::Origin if bookmark.origin.len() == lines.len() => Some(&bookmark.origin),
+        _ => None,
+    };
+    let number = |idx: usize| origin.map_or(idx + 1, |origin| origin[idx]);
+    let width = (0..lines.len())
+        .map(|idx| number(idx).to_string().len())
+        .max()
+        .unwrap_or(1);
+
+    let mut result = Vec::new();
+    for (idx, line) in lines.iter().enumerate() {
+        let elided = match origin {
+            // Origin lines edited out of order elide nothing
+            Some(origin) if idx > 0 => origin[idx].saturating_sub(origin[idx - 1] + 1),
+            _ => 0,
+        };
+        if elided > 0 {
+            let lines = if elided == 1 { "line" } else { "lines" };
+            result.push(format!("{:>width$} ┊ {} {} elided", "⋮", elided, lines));
+        }
+
+        result.push(format!("{:>width$} │ {}", number(idx), line));
+    }
+
+    result
+}
+
+/// Box with the name of `bookmark`, its language and tags, and the file and
+/// lines it was captured from.
+pub fn header(bookmark: &Bookmark) -> Vec<String> {
+    let mut details = format!("Language: {}", bookmark.lang);
+    if !bookmark.tags.is_empty() {
+        details.push_str(&format!(" · Tags: {}", bookmark.tags.join(", ")));
+    }
+
+    let mut rows = vec![bookmark.name.clone(), details];
+    if !bookmark.source.is_empty() {
+        let mut source = format!("Source: {}", bookmark.source);
+        if !bookmark.origin.is_empty() {
+            source.push_str(&format!(":{}", utils::format_ranges(&bookmark.origin)));
+        }
+        rows.push(source);
+    }
+
+    let width = rows
+        .iter()
+        .map(|row| row.chars().count())
+        .max()
+        .unwrap_or(0);
+    let border = "─".repeat(width + 2);
+
+    let mut result = vec![format!("┌{}┐", border)];
+    for row in rows {
+        let padding = width - row.chars().count();
+        result.push(format!("│ {}{} │", row, " ".repeat(padding)));
+    }
+    result.push(format!("└{}┘", border));
+    result
+}
+
+/// What bookmarks are listed by.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortKey {
+    Name,
+    Lang,
+    Lines,
+    Updated,
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        match key {
+            "name" => Ok(SortKey::Name),
+            "lang" => Ok(SortKey::Lang),
+            "lines" => Ok(SortKey::Lines),
+            "updated" => Ok(SortKey::Updated),
+            _ => Err(format!(
+                "Unknown sort key '{}', expected name, lang, lines or updated",
+                key
+            )),
+        }
+    }
+}
+
+/// Sorts `bookmarks` by `key` in ascending order, and by name when they are
+/// equal.
+pub fn sort(bookmarks: &mut [Bookmark], key: SortKey) {
+    bookmarks.sort_by(|a, b| {
+        let order = match key {
+            SortKey::Name => std::cmp::Ordering::Equal,
+            SortKey::Lang => a.lang.name().cmp(b.lang.name()),
+            SortKey::Lines => a.content.len().cmp(&b.content.len()),
+            SortKey::Updated => a.updated.cmp(&b.updated),
+        };
+        order.then_with(|| a.name.cmp(&b.name))
+    });
+}
+
+/// Number of pages of `size` items needed for `len` items.
+pub fn pages(len: usize, size: usize) -> usize {
+    len.div_ceil(size.max(1))
+}
+
+/// Items on page `number` when split in pages of `size`, starting at 1.
+/// Pages past the last one are empty.
+pub fn page<T>(items: &[T], size: usize, number: usize) -> &[T] {
+    let size = size.max(1);
+    let start = number
+        .saturating_sub(1)
+        .saturating_mul(size)
+        .min(items.len());
+    let end = start.saturating_add(size).min(items.len());
+    &items[start..end]
+}
+
+/// One row per bookmark with its name, language, number of lines, tags and
+/// the date it was last updated, under a row of column titles.
+pub fn table(bookmarks: &[Bookmark]) -> Vec<String> {
+    let titles = ["NAME", "LANG", "LINES", "TAGS", "UPDATED"].map(String::from);
+    let mut rows = vec![titles];
+    for bookmark in bookmarks {
+        let updated = match bookmark.updated {
+            0 => String::from("-"),
+            updated => utils::format_date(updated),
+        };
+        rows.push([
+            bookmark.name.clone(),
+            bookmark.lang.to_string(),
+            bookmark.content.len().to_string(),
+            bookmark.tags.join(", "),
+            updated,
+        ]);
+    }
+
+    let mut widths = [0; 5];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    rows.iter()
+        .map(|row| {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(widths)
+                .enumerate()
+                .map(|(column, (cell, width))| match column {
+                    // Numbers are aligned to the right
+                    2 => format!("{:>width$}", cell),
+                    _ => format!("{:<width$}", cell),
+                })
+                .collect();
+            cells.join("  ").trim_end().to_string()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{gutter, header, page, pages, sort, table, LineNumbers, SortKey};
+    use crate::bookmark::Bookmark;
+    use crate::language::Language;
+
+    fn bookmark() -> Bookmark {
+        let content: Vec<String> = ["fn one() {", "    1", "}"]
+            .iter()
+            .map(|line| line.to_string())
+            .collect();
+        let mut bookmark = Bookmark::new("rust/one", &content, Language::Rust);
+        bookmark.source = String::from("src/lib.rs");
+        bookmark.origin = vec![8, 11, 12];
+        bookmark
+    }
+
+    #[test]
+    fn numbers_lines() {
+        let bookmark = bookmark();
+        assert_eq!(
+            gutter(&bookmark, &bookmark.content, LineNumbers::Relative),
+            vec!["1 │ fn one() {", "2 │     1", "3 │ }"]
+        );
+        assert_eq!(
+            gutter(&bookmark, &bookmark.content, LineNumbers::Origin),
+            vec![
+                " 8 │ fn one() {",
+                " ⋮ ┊ 2 lines elided",
+                "11 │     1",
+                "12 │ }"
+            ]
+        );
+
+        // Without origin lines the bookmark is numbered from 1
+        let mut edited = bookmark.clone();
+        edited.set_content(&bookmark.content[..2]);
+        assert_eq!(
+            gutter(&edited, &edited.content, LineNumbers::Origin),
+            vec!["1 │ fn one() {", "2 │     1"]
+        );
+
+        let mut unordered = bookmark.clone();
+        unordered.origin = vec![12, 11, 11];
+        assert_eq!(
+            gutter(&unordered, &unordered.content, LineNumbers::Origin),
+            vec!["12 │ fn one() {", "11 │     1", "11 │ }"]
+        );
+    }
+
+    #[test]
+    fn draws_header_box() {
+        let mut bookmark = bookmark();
+        bookmark.tags = vec![String::from("math")];
+        assert_eq!(
+            header(&bookmark),
+            vec![
+                "┌─────────────────────────────┐",
+                "│ rust/one                    │",
+                "│ Language: Rust · Tags: math │",
+                "│ Source: src/lib.rs:8,11-12  │",
+                "└─────────────────────────────┘",
+            ]
+        );
+    }
+
+    #[test]
+    fn sorts_and_pages_bookmarks() {
+        let mut bookmarks = vec![bookmark(), bookmark(), bookmark()];
+        bookmarks[0].name = String::from("b");
+        bookmarks[0].updated = 20;
+        bookmarks[1].name = String::from("c");
+        bookmarks[1].content.truncate(1);
+        bookmarks[1].updated = 10;
+        bookmarks[2].name = String::from("a");
+        bookmarks[2].updated = 30;
+        let names = |bookmarks: &[_]| -> Vec<String> {
+            bookmarks
+                .iter()
+                .map(|bookmark: &Bookmark| bookmark.name.clone())
+                .collect()
+        };
+
+        sort(&mut bookmarks, SortKey::Name);
+        assert_eq!(names(&bookmarks), ["a", "b", "c"]);
+        sort(&mut bookmarks, SortKey::Updated);
+        assert_eq!(names(&bookmarks), ["c", "b", "a"]);
+        sort(&mut bookmarks, SortKey::Lines);
+        assert_eq!(names(&bookmarks), ["c", "a", "b"]);
+
+        assert_eq!(pages(3, 2), 2);
+        assert_eq!(pages(0, 2), 0);
+        assert_eq!(names(page(&bookmarks, 2, 1)), ["c", "a"]);
+        assert_eq!(names(page(&bookmarks, 2, 2)), ["b"]);
+        assert!(page(&bookmarks, 2, 3).is_empty());
+    }
+
+    #[test]
+    fn lists_bookmarks_in_table() {
+        let mut first = bookmark();
+        first.tags = vec![String::from("math"), String::from("io")];
+        first.updated = 1_710_000_000;
+        let mut second = bookmark();
+        second.name = String::from("python/parse_arguments");
+        second.lang = Language::Python;
+        second.updated = 0;
+
+        assert_eq!(
+            table(&[first, second]),
+            vec![
+                "NAME                    LANG    LINES  TAGS      UPDATED",
+                "rust/one                Rust        3  math, io  2024-03-09",
+                "python/parse_arguments  Python      3            -",
+            ]
+        );
+    }
+}